@@ -1,24 +1,49 @@
+use std::cmp::Ordering;
 use std::fmt;
-
-#[derive(Debug, Clone, PartialEq, Eq)]
+use std::hash::{Hash, Hasher};
+use std::slice;
+use std::vec;
+
+/// A boolean expression over variables of type `T`.
+///
+/// With the `serde` feature, an expression is represented as
+/// `{"any": [..]}`, `{"all": [..]}`, `{"not": ..}`, `{"var": ..}`, `true` or `false`.
+///
+/// Expressions are ordered by variant in declaration order, then by children or value.
+///
+/// Cloning, comparing, hashing and printing do not recurse,
+/// so deeply nested expressions cannot overflow the stack.
+/// Dropping does, so deeply nested expressions should be dropped with [`Expr::drop_iterative`].
+#[derive(Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
 pub enum Expr<T> {
     Any(Any<T>),
     All(All<T>),
     Not(Not<T>),
     Var(Var<T>),
+    #[cfg_attr(feature = "serde", serde(untagged))]
     Const(bool),
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(transparent))]
 pub struct Any<T>(pub Vec<Expr<T>>);
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(transparent))]
 pub struct All<T>(pub Vec<Expr<T>>);
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(transparent))]
 pub struct Not<T>(pub Box<Expr<T>>);
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(transparent))]
 pub struct Var<T>(pub T);
 
 pub fn expr<T>(x: impl Into<Expr<T>>) -> Expr<T> {
@@ -202,6 +227,340 @@ impl<T> Expr<T> {
     }
 }
 
+impl<T> Expr<T> {
+    /// Returns the distinct variables in order of first occurrence.
+    pub fn vars(&self) -> Vec<&T>
+    where
+        T: Eq,
+    {
+        let mut ans: Vec<&T> = Vec::new();
+        let mut stack = vec![self];
+        while let Some(expr) = stack.pop() {
+            match expr {
+                Expr::Any(Any(list)) | Expr::All(All(list)) => stack.extend(list.iter().rev()),
+                Expr::Not(Not(not)) => stack.push(not),
+                Expr::Var(Var(x)) => {
+                    if !ans.contains(&x) {
+                        ans.push(x);
+                    }
+                }
+                Expr::Const(_) => {}
+            }
+        }
+        ans
+    }
+}
+
+impl<T> Expr<T> {
+    /// Converts the variables with `f`, keeping the structure.
+    pub fn map<U>(self, f: &mut impl FnMut(T) -> U) -> Expr<U> {
+        fold_owned(self, &mut |node| node.into_expr(&mut *f))
+    }
+}
+
+/// A node whose children have been folded into `R`.
+pub(crate) enum Node<T, R> {
+    Any(Vec<R>),
+    All(Vec<R>),
+    Not(R),
+    Var(T),
+    Const(bool),
+}
+
+impl<T, U> Node<T, Expr<U>> {
+    pub(crate) fn into_expr(self, f: impl FnOnce(T) -> U) -> Expr<U> {
+        match self {
+            Node::Any(list) => Expr::Any(Any(list)),
+            Node::All(list) => Expr::All(All(list)),
+            Node::Not(not) => Expr::Not(Not(Box::new(not))),
+            Node::Var(x) => Expr::Var(Var(f(x))),
+            Node::Const(b) => Expr::Const(b),
+        }
+    }
+}
+
+/// Folds the expression bottom-up with an explicit stack.
+pub(crate) fn fold_ref<'a, T, R>(expr: &'a Expr<T>, f: &mut impl FnMut(Node<&'a T, R>) -> R) -> R {
+    enum Frame<'a, T, R> {
+        List {
+            any: bool,
+            rest: slice::Iter<'a, Expr<T>>,
+            done: Vec<R>,
+        },
+        Not,
+    }
+
+    let mut stack: Vec<Frame<'a, T, R>> = Vec::new();
+    let mut cur = expr;
+    loop {
+        let mut val = loop {
+            match cur {
+                Expr::Any(Any(list)) | Expr::All(All(list)) => {
+                    let any = cur.is_any();
+                    let mut rest = list.iter();
+                    let Some(first) = rest.next() else {
+                        break f(if any {
+                            Node::Any(Vec::new())
+                        } else {
+                            Node::All(Vec::new())
+                        });
+                    };
+                    let done = Vec::with_capacity(list.len());
+                    stack.push(Frame::List { any, rest, done });
+                    cur = first;
+                }
+                Expr::Not(Not(not)) => {
+                    stack.push(Frame::Not);
+                    cur = not;
+                }
+                Expr::Var(Var(x)) => break f(Node::Var(x)),
+                Expr::Const(b) => break f(Node::Const(*b)),
+            }
+        };
+        loop {
+            match stack.last_mut() {
+                None => return val,
+                Some(Frame::Not) => {
+                    stack.pop();
+                    val = f(Node::Not(val));
+                }
+                Some(Frame::List { rest, done, .. }) => {
+                    done.push(val);
+                    if let Some(next) = rest.next() {
+                        cur = next;
+                        break;
+                    }
+                    let Some(Frame::List { any, done, .. }) = stack.pop() else {
+                        unreachable!()
+                    };
+                    val = f(if any {
+                        Node::Any(done)
+                    } else {
+                        Node::All(done)
+                    });
+                }
+            }
+        }
+    }
+}
+
+/// Folds the expression bottom-up with an explicit stack, consuming it.
+pub(crate) fn fold_owned<T, R>(expr: Expr<T>, f: &mut impl FnMut(Node<T, R>) -> R) -> R {
+    enum Frame<T, R> {
+        List {
+            any: bool,
+            rest: vec::IntoIter<Expr<T>>,
+            done: Vec<R>,
+        },
+        Not,
+    }
+
+    let mut stack: Vec<Frame<T, R>> = Vec::new();
+    let mut cur = expr;
+    loop {
+        let mut val = loop {
+            let (any, list) = match cur {
+                Expr::Any(Any(list)) => (true, list),
+                Expr::All(All(list)) => (false, list),
+                Expr::Not(Not(not)) => {
+                    stack.push(Frame::Not);
+                    cur = *not;
+                    continue;
+                }
+                Expr::Var(Var(x)) => break f(Node::Var(x)),
+                Expr::Const(b) => break f(Node::Const(b)),
+            };
+            let done = Vec::with_capacity(list.len());
+            let mut rest = list.into_iter();
+            let Some(first) = rest.next() else {
+                break f(if any {
+                    Node::Any(done)
+                } else {
+                    Node::All(done)
+                });
+            };
+            stack.push(Frame::List { any, rest, done });
+            cur = first;
+        };
+        loop {
+            match stack.last_mut() {
+                None => return val,
+                Some(Frame::Not) => {
+                    stack.pop();
+                    val = f(Node::Not(val));
+                }
+                Some(Frame::List { rest, done, .. }) => {
+                    done.push(val);
+                    if let Some(next) = rest.next() {
+                        cur = next;
+                        break;
+                    }
+                    let Some(Frame::List { any, done, .. }) = stack.pop() else {
+                        unreachable!()
+                    };
+                    val = f(if any {
+                        Node::Any(done)
+                    } else {
+                        Node::All(done)
+                    });
+                }
+            }
+        }
+    }
+}
+
+impl<T> Clone for Expr<T>
+where
+    T: Clone,
+{
+    fn clone(&self) -> Self {
+        fold_ref(self, &mut |node| node.into_expr(T::clone))
+    }
+}
+
+impl<T> PartialEq for Expr<T>
+where
+    T: PartialEq,
+{
+    fn eq(&self, other: &Self) -> bool {
+        let mut stack = vec![(self, other)];
+        while let Some(pair) = stack.pop() {
+            match pair {
+                (Expr::Any(Any(lhs)), Expr::Any(Any(rhs)))
+                | (Expr::All(All(lhs)), Expr::All(All(rhs))) => {
+                    if lhs.len() != rhs.len() {
+                        return false;
+                    }
+                    stack.extend(lhs.iter().zip(rhs));
+                }
+                (Expr::Not(Not(lhs)), Expr::Not(Not(rhs))) => stack.push((lhs, rhs)),
+                (Expr::Var(Var(lhs)), Expr::Var(Var(rhs))) => {
+                    if lhs != rhs {
+                        return false;
+                    }
+                }
+                (Expr::Const(lhs), Expr::Const(rhs)) => {
+                    if lhs != rhs {
+                        return false;
+                    }
+                }
+                _ => return false,
+            }
+        }
+        true
+    }
+}
+
+impl<T> Expr<T> {
+    fn rank(&self) -> u8 {
+        match self {
+            Expr::Any(_) => 0,
+            Expr::All(_) => 1,
+            Expr::Not(_) => 2,
+            Expr::Var(_) => 3,
+            Expr::Const(_) => 4,
+        }
+    }
+}
+
+/// Compares lexicographically in pre-order, comparing variables with `cmp_var`.
+fn compare<T>(
+    lhs: &Expr<T>,
+    rhs: &Expr<T>,
+    cmp_var: &mut impl FnMut(&T, &T) -> Option<Ordering>,
+) -> Option<Ordering> {
+    enum Task<'a, T> {
+        Pair(&'a Expr<T>, &'a Expr<T>),
+        Len(usize, usize),
+    }
+
+    let mut stack = vec![Task::Pair(lhs, rhs)];
+    while let Some(task) = stack.pop() {
+        let ord = match task {
+            Task::Len(lhs, rhs) => lhs.cmp(&rhs),
+            Task::Pair(lhs, rhs) => match (lhs, rhs) {
+                (Expr::Any(Any(lhs)), Expr::Any(Any(rhs)))
+                | (Expr::All(All(lhs)), Expr::All(All(rhs))) => {
+                    stack.push(Task::Len(lhs.len(), rhs.len()));
+                    stack.extend(lhs.iter().zip(rhs).rev().map(|(l, r)| Task::Pair(l, r)));
+                    continue;
+                }
+                (Expr::Not(Not(lhs)), Expr::Not(Not(rhs))) => {
+                    stack.push(Task::Pair(lhs, rhs));
+                    continue;
+                }
+                (Expr::Var(Var(lhs)), Expr::Var(Var(rhs))) => cmp_var(lhs, rhs)?,
+                (Expr::Const(lhs), Expr::Const(rhs)) => lhs.cmp(rhs),
+                _ => lhs.rank().cmp(&rhs.rank()),
+            },
+        };
+        if ord.is_ne() {
+            return Some(ord);
+        }
+    }
+    Some(Ordering::Equal)
+}
+
+impl<T> PartialOrd for Expr<T>
+where
+    T: PartialOrd,
+{
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        compare(self, other, &mut T::partial_cmp)
+    }
+}
+
+impl<T> Ord for Expr<T>
+where
+    T: Ord,
+{
+    fn cmp(&self, other: &Self) -> Ordering {
+        compare(self, other, &mut |lhs, rhs| Some(lhs.cmp(rhs))).unwrap_or(Ordering::Equal)
+    }
+}
+
+impl<T> Hash for Expr<T>
+where
+    T: Hash,
+{
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        let mut stack = vec![self];
+        while let Some(expr) = stack.pop() {
+            state.write_u8(expr.rank());
+            match expr {
+                Expr::Any(Any(list)) | Expr::All(All(list)) => {
+                    state.write_usize(list.len());
+                    stack.extend(list.iter().rev());
+                }
+                Expr::Not(Not(not)) => stack.push(not),
+                Expr::Var(Var(x)) => x.hash(state),
+                Expr::Const(b) => b.hash(state),
+            }
+        }
+    }
+}
+
+/// Drops the expressions with an explicit stack,
+/// detaching the children of each node before it is dropped.
+pub(crate) fn drop_deep<T>(mut stack: Vec<Expr<T>>) {
+    while let Some(expr) = stack.pop() {
+        match expr {
+            Expr::Any(Any(list)) | Expr::All(All(list)) => stack.extend(list),
+            Expr::Not(Not(not)) => stack.push(*not),
+            Expr::Var(_) | Expr::Const(_) => {}
+        }
+    }
+}
+
+impl<T> Expr<T> {
+    /// Drops the expression with an explicit stack.
+    ///
+    /// The implicit drop recurses, so use this for expressions nested too deeply for the call stack.
+    pub fn drop_iterative(self) {
+        drop_deep(vec![self]);
+    }
+}
+
 impl<T> From<Any<T>> for Expr<T> {
     fn from(any: Any<T>) -> Self {
         Expr::Any(any)
@@ -331,26 +690,257 @@ where
     T: fmt::Display,
 {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match self {
-            Expr::Any(Any(any)) => fmt_list(f, "any", any),
-            Expr::All(All(all)) => fmt_list(f, "all", all),
-            Expr::Not(Not(not)) => write!(f, "not({not})"),
-            Expr::Var(Var(x)) => write!(f, "{x}"),
-            Expr::Const(b) => write!(f, "{b}"),
+        enum Item<'a, T> {
+            Expr(&'a Expr<T>),
+            Str(&'static str),
+        }
+
+        let mut stack = vec![Item::Expr(self)];
+        while let Some(item) = stack.pop() {
+            let expr = match item {
+                Item::Expr(expr) => expr,
+                Item::Str(s) => {
+                    f.write_str(s)?;
+                    continue;
+                }
+            };
+            match expr {
+                Expr::Any(Any(list)) | Expr::All(All(list)) => {
+                    f.write_str(if expr.is_any() { "any(" } else { "all(" })?;
+                    stack.push(Item::Str(")"));
+                    for (i, e) in list.iter().enumerate().rev() {
+                        stack.push(Item::Expr(e));
+                        if i != 0 {
+                            stack.push(Item::Str(", "));
+                        }
+                    }
+                }
+                Expr::Not(Not(not)) => {
+                    f.write_str("not(")?;
+                    stack.push(Item::Str(")"));
+                    stack.push(Item::Expr(not));
+                }
+                Expr::Var(Var(x)) => write!(f, "{x}")?,
+                Expr::Const(b) => write!(f, "{b}")?,
+            }
         }
+        Ok(())
     }
 }
 
-fn fmt_list<T>(f: &mut fmt::Formatter<'_>, name: &str, list: &[Expr<T>]) -> fmt::Result
+/// Prints the same output as a derived `Debug`, with an explicit stack.
+impl<T> fmt::Debug for Expr<T>
 where
-    T: fmt::Display,
+    T: fmt::Debug,
 {
-    write!(f, "{name}(")?;
-    for (i, e) in list.iter().enumerate() {
-        if i != 0 {
-            write!(f, ", ")?;
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        enum Item<'a, T> {
+            Expr(&'a Expr<T>, usize),
+            Leaf(&'a dyn fmt::Debug, usize),
+            Str(&'static str),
+            Indent(usize),
+        }
+
+        let pretty = f.alternate();
+        let (open, close) = if pretty { ("(\n", ",\n") } else { ("(", "") };
+
+        let mut stack = vec![Item::Expr(self, 0)];
+        while let Some(item) = stack.pop() {
+            let (expr, level) = match item {
+                Item::Expr(expr, level) => (expr, level),
+                // Indents the lines of the value as nested derived impls do.
+                Item::Leaf(x, level) if pretty => {
+                    let pad = format!("\n{}", "    ".repeat(level));
+                    f.write_str(&format!("{x:#?}").replace('\n', &pad))?;
+                    continue;
+                }
+                Item::Leaf(x, _) => {
+                    x.fmt(f)?;
+                    continue;
+                }
+                Item::Str(s) => {
+                    f.write_str(s)?;
+                    continue;
+                }
+                Item::Indent(level) => {
+                    f.write_str(&"    ".repeat(level))?;
+                    continue;
+                }
+            };
+
+            // The tuple names wrapping the body, as in `Any(Any([..]))` or `Const(true)`.
+            let names: &[&str] = match expr {
+                Expr::Any(_) => &["Any", "Any"],
+                Expr::All(_) => &["All", "All"],
+                Expr::Not(_) => &["Not", "Not"],
+                Expr::Var(_) => &["Var", "Var"],
+                Expr::Const(_) => &["Const"],
+            };
+            let inner = level + names.len();
+
+            let mut items = Vec::new();
+            for (i, name) in names.iter().enumerate() {
+                if i != 0 {
+                    items.push(Item::Indent(level + i));
+                }
+                items.extend([Item::Str(name), Item::Str(open)]);
+            }
+            items.push(Item::Indent(inner));
+            match expr {
+                Expr::Any(Any(list)) | Expr::All(All(list)) => {
+                    items.push(Item::Str("["));
+                    for (i, e) in list.iter().enumerate() {
+                        if pretty {
+                            items.push(Item::Str(if i == 0 { "\n" } else { ",\n" }));
+                        } else if i != 0 {
+                            items.push(Item::Str(", "));
+                        }
+                        items.extend([Item::Indent(inner + 1), Item::Expr(e, inner + 1)]);
+                    }
+                    if pretty && !list.is_empty() {
+                        items.extend([Item::Str(",\n"), Item::Indent(inner)]);
+                    }
+                    items.push(Item::Str("]"));
+                }
+                Expr::Not(Not(not)) => items.push(Item::Expr(not, inner)),
+                Expr::Var(Var(x)) => items.push(Item::Leaf(x, inner)),
+                Expr::Const(b) => items.push(Item::Leaf(b, inner)),
+            }
+            for i in (0..names.len()).rev() {
+                items.extend([Item::Str(close), Item::Indent(level + i), Item::Str(")")]);
+            }
+            if !pretty {
+                items.retain(|item| !matches!(item, Item::Indent(_)));
+            }
+            stack.extend(items.into_iter().rev());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Alternates `not` and single-element `any` around a variable, `n` levels deep.
+    fn deep(n: usize) -> Expr<u32> {
+        let mut x = expr(var(0));
+        for i in 0..n {
+            x = if i % 2 == 0 {
+                expr(not(x))
+            } else {
+                expr(any((x,)))
+            };
+        }
+        x
+    }
+
+    #[test]
+    fn deeply_nested() {
+        let x = deep(200_000);
+        let y = x.clone();
+        assert!(x == y);
+        let w = deep(200_001);
+        assert!(x != w);
+        assert_eq!(x.vars(), [&0]);
+
+        let text = x.to_string();
+        assert!(text.starts_with("any(not(any(not("));
+        assert_eq!(text.len(), 100_000 * "any(not())".len() + 1);
+
+        let z = y.map(&mut |v| v + 1);
+        assert_eq!(z.vars(), [&1]);
+
+        [x, z, w].into_iter().for_each(Expr::drop_iterative);
+    }
+
+    #[test]
+    fn debug() {
+        #[allow(dead_code)]
+        mod derived {
+            #[derive(Debug)]
+            pub enum Expr {
+                Any(Any),
+                Not(Not),
+                Var(Var),
+                Const(bool),
+            }
+            #[derive(Debug)]
+            pub struct Any(pub Vec<Expr>);
+            #[derive(Debug)]
+            pub struct Not(pub Box<Expr>);
+            #[derive(Debug)]
+            pub struct Var(pub &'static str);
+        }
+
+        let x: Expr<&str> = expr(any((var("a\nb"), not(const_(true)), any(()))));
+        let y = derived::Expr::Any(derived::Any(vec![
+            derived::Expr::Var(derived::Var("a\nb")),
+            derived::Expr::Not(derived::Not(Box::new(derived::Expr::Const(true)))),
+            derived::Expr::Any(derived::Any(vec![])),
+        ]));
+        assert_eq!(format!("{x:?}"), format!("{y:?}"));
+        assert_eq!(format!("{x:#?}"), format!("{y:#?}"));
+        assert_eq!(
+            format!("{x:?}"),
+            r#"Any(Any([Var(Var("a\nb")), Not(Not(Const(true))), Any(Any([]))]))"#
+        );
+
+        let x = deep(200_000);
+        let s = format!("{x:?}");
+        assert!(s.starts_with("Any(Any([Not(Not(Any(Any([Not(Not("));
+        x.drop_iterative();
+    }
+
+    #[test]
+    fn ordering() {
+        let a: Expr<u32> = expr(any((var(1), var(2))));
+        let b: Expr<u32> = expr(any((var(1), var(2), var(0))));
+        let c: Expr<u32> = expr(any((var(2),)));
+        assert!(a < b && b < c);
+        assert!(expr(all(())) > c);
+        assert!(expr(var(9)) < const_(false));
+        assert_eq!(a.cmp(&a.clone()), Ordering::Equal);
+
+        let lhs = deep(100_000);
+        let rhs = deep(100_001);
+        assert!(lhs < rhs);
+        let mut set = std::collections::HashSet::new();
+        for item in [lhs.clone(), deep(100_000), deep(3)] {
+            if let Some(dup) = set.replace(item) {
+                dup.drop_iterative();
+            }
         }
-        write!(f, "{e}")?;
+        assert_eq!(set.len(), 2);
+
+        lhs.drop_iterative();
+        rhs.drop_iterative();
+        set.into_iter().for_each(Expr::drop_iterative);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_json() {
+        let x: Expr<&str> = expr(any((var("a"), all((not(var("b")), const_(true))), all(()))));
+        let json = serde_json::to_string(&x).unwrap();
+        assert_eq!(
+            json,
+            r#"{"any":[{"var":"a"},{"all":[{"not":{"var":"b"}},true]},{"all":[]}]}"#
+        );
+        assert_eq!(serde_json::from_str::<Expr<&str>>(&json).unwrap(), x);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_json_pred() {
+        use crate::cfg::ast::{Pred, flag, target_os};
+
+        let x = crate::cfg::ast::expr(any((flag("unix"), target_os("linux"))));
+        let json = serde_json::to_string(&x).unwrap();
+        assert_eq!(
+            json,
+            r#"{"any":[{"var":{"key":"unix"}},{"var":{"key":"target_os","value":"linux"}}]}"#
+        );
+        assert_eq!(serde_json::from_str::<Expr<Pred>>(&json).unwrap(), x);
     }
-    write!(f, ")")
 }
@@ -0,0 +1,141 @@
+//! Bit-parallel evaluation of one expression over many assignments.
+
+use crate::ast::{Expr, Node, fold_ref};
+use crate::utils::index_vars;
+
+use std::hash::Hash;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Op {
+    Var(usize),
+    Const(u64),
+    Any(usize),
+    All(usize),
+    Not,
+}
+
+/// An expression lowered to a postfix program over 64-bit lanes.
+///
+/// Bit `i` of every word belongs to the `i`-th assignment of a batch of 64.
+#[derive(Debug, Clone)]
+pub struct BatchEval<'a, T> {
+    vars: Vec<&'a T>,
+    ops: Vec<Op>,
+}
+
+impl<'a, T> BatchEval<'a, T>
+where
+    T: Eq + Hash,
+{
+    #[must_use]
+    pub fn new(expr: &'a Expr<T>) -> Self {
+        let (vars, indexed) = index_vars(expr);
+        let mut ops = Vec::new();
+        fold_ref(&indexed, &mut |node| {
+            ops.push(match node {
+                Node::Any(list) => Op::Any(list.len()),
+                Node::All(list) => Op::All(list.len()),
+                Node::Not(()) => Op::Not,
+                Node::Var(&i) => Op::Var(i),
+                Node::Const(b) => Op::Const(if b { !0 } else { 0 }),
+            });
+        });
+        indexed.drop_iterative();
+        Self { vars, ops }
+    }
+}
+
+impl<'a, T> BatchEval<'a, T> {
+    /// Returns the variables in the order of the input words.
+    #[must_use]
+    pub fn vars(&self) -> &[&'a T] {
+        &self.vars
+    }
+
+    /// Evaluates 64 assignments at once, given one word per variable.
+    ///
+    /// # Panics
+    /// Panics if the number of words differs from the number of variables.
+    #[must_use]
+    pub fn eval_words(&self, words: &[u64]) -> u64 {
+        assert_eq!(
+            words.len(),
+            self.vars.len(),
+            "expected one word per variable"
+        );
+
+        let mut stack: Vec<u64> = Vec::with_capacity(self.ops.len());
+        for op in &self.ops {
+            let word = match *op {
+                Op::Var(i) => words[i],
+                Op::Const(w) => w,
+                Op::Any(n) => stack.drain(stack.len() - n..).fold(0, |acc, w| acc | w),
+                Op::All(n) => stack.drain(stack.len() - n..).fold(!0, |acc, w| acc & w),
+                Op::Not => !stack.pop().unwrap(),
+            };
+            stack.push(word);
+        }
+        stack.pop().unwrap()
+    }
+
+    /// Evaluates the expression for every configuration, where `f` tells whether a variable holds in it.
+    ///
+    /// Returns a bitset with the result for `configs[i]` at bit `i % 64` of word `i / 64`.
+    pub fn eval_many<C, F>(&self, configs: &[C], f: &F) -> Vec<u64>
+    where
+        F: Fn(&C, &T) -> bool,
+    {
+        let mut words = vec![0; self.vars.len()];
+        configs
+            .chunks(64)
+            .map(|chunk| {
+                for (word, var) in words.iter_mut().zip(&self.vars) {
+                    *word = chunk
+                        .iter()
+                        .enumerate()
+                        .filter(|(_, c)| f(c, var))
+                        .fold(0, |acc, (i, _)| acc | (1 << i));
+                }
+                let mask = if chunk.len() == 64 {
+                    !0
+                } else {
+                    (1 << chunk.len()) - 1
+                };
+                self.eval_words(&words) & mask
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::{all, any, const_, expr, not, var};
+    use crate::eval::eval_with;
+
+    #[test]
+    fn words() {
+        let x = expr(any((all((var('a'), not(var('b')))), const_(false))));
+        let batch = BatchEval::new(&x);
+        assert_eq!(batch.vars(), [&'a', &'b']);
+        assert_eq!(batch.eval_words(&[0b1100, 0b1010]), 0b0100);
+    }
+
+    #[test]
+    fn matches_eval_with() {
+        let x = expr(all((
+            any((var(0), not(var(1)), var(2))),
+            not(all((var(3), var(0)))),
+            any((var(4), const_(true))),
+        )));
+        let configs: Vec<u32> = (0..200).map(|i| i * 7 % 32).collect();
+        let bits = BatchEval::new(&x).eval_many(&configs, &|c, v| (c >> v) & 1 == 1);
+
+        assert_eq!(bits.len(), 4);
+        for (i, c) in configs.iter().enumerate() {
+            let expected = eval_with(&x, &|v| (c >> v) & 1 == 1);
+            assert_eq!((bits[i / 64] >> (i % 64)) & 1 == 1, expected, "config {c}");
+        }
+        assert_eq!(bits[3] >> 8, 0);
+    }
+}
@@ -0,0 +1,238 @@
+#![allow(clippy::missing_errors_doc)]
+
+use super::ast::{All, Any, Expr, Not, Pred, Var, flag};
+
+use crate::visit_mut::{VisitMut, walk_mut_postorder};
+
+use std::error::Error;
+use std::fmt;
+
+/// Named cfg expressions, like `linux_like = any(target_os = "linux", target_os = "android")`.
+///
+/// An alias is referred to by a flag predicate with the same name.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CfgAliases {
+    aliases: Vec<(String, Expr)>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CycleError {
+    /// The aliases on the cycle, starting and ending with the same name.
+    pub cycle: Vec<String>,
+}
+
+impl fmt::Display for CycleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "cyclic cfg alias: {}", self.cycle.join(" -> "))
+    }
+}
+
+impl Error for CycleError {}
+
+impl CfgAliases {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Defines an alias, replacing any previous definition with the same name.
+    pub fn define(&mut self, name: impl Into<String>, expr: impl Into<Expr>) {
+        let name = name.into();
+        let expr = expr.into();
+        match self.aliases.iter_mut().find(|(n, _)| *n == name) {
+            Some(slot) => slot.1 = expr,
+            None => self.aliases.push((name, expr)),
+        }
+    }
+
+    #[must_use]
+    pub fn get(&self, name: &str) -> Option<&Expr> {
+        self.aliases.iter().find(|(n, _)| n == name).map(|(_, e)| e)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &Expr)> {
+        self.aliases.iter().map(|(n, e)| (n.as_str(), e))
+    }
+
+    fn get_flag(&self, pred: &Pred) -> Option<(&str, &Expr)> {
+        if pred.value.is_some() {
+            return None;
+        }
+        self.aliases
+            .iter()
+            .find(|(n, _)| *n == pred.key)
+            .map(|(n, e)| (n.as_str(), e))
+    }
+
+    /// Replaces alias flags by their definitions, recursively.
+    pub fn expand(&self, expr: &mut Expr) -> Result<(), CycleError> {
+        self.expand_in(expr, &mut Vec::new())
+    }
+
+    fn expand_in<'a>(
+        &'a self,
+        expr: &mut Expr,
+        stack: &mut Vec<&'a str>,
+    ) -> Result<(), CycleError> {
+        match expr {
+            Expr::Any(Any(list)) | Expr::All(All(list)) => {
+                for e in list {
+                    self.expand_in(e, stack)?;
+                }
+            }
+            Expr::Not(Not(not)) => self.expand_in(not, stack)?,
+            Expr::Var(Var(pred)) => {
+                if let Some((name, def)) = self.get_flag(pred) {
+                    if let Some(pos) = stack.iter().position(|&n| n == name) {
+                        let mut cycle: Vec<String> =
+                            stack[pos..].iter().map(|&n| n.to_owned()).collect();
+                        cycle.push(name.to_owned());
+                        return Err(CycleError { cycle });
+                    }
+
+                    let mut def = def.clone();
+                    stack.push(name);
+                    self.expand_in(&mut def, stack)?;
+                    stack.pop();
+                    *expr = def;
+                }
+            }
+            Expr::Const(_) => {}
+        }
+        Ok(())
+    }
+
+    /// Replaces sub-expressions equal to an alias definition by the alias flag.
+    ///
+    /// An `any` or `all` also folds when it contains every item of an alias defined with the same operator,
+    /// e.g. `any(target_os = "linux", target_os = "android", target_os = "macos")` becomes `any(linux_like, target_os = "macos")`.
+    /// Aliases are tried in definition order, and each alias folds at most once per sub-expression,
+    /// so cyclic aliases terminate.
+    pub fn fold(&self, expr: &mut Expr) {
+        FoldAliases(self).visit_mut_expr(expr);
+    }
+
+    fn fold_once<'a>(&'a self, expr: &mut Expr, folded: &[&str]) -> Option<&'a str> {
+        for (name, def) in &self.aliases {
+            if folded.contains(&name.as_str()) {
+                continue;
+            }
+            if expr == def {
+                *expr = Expr::Var(Var(flag(name.as_str())));
+                return Some(name);
+            }
+
+            let ((Expr::Any(Any(list)), Expr::Any(Any(def_list)))
+            | (Expr::All(All(list)), Expr::All(All(def_list)))) = (&mut *expr, def)
+            else {
+                continue;
+            };
+
+            if def_list.len() < 2 || !def_list.iter().all(|x| list.contains(x)) {
+                continue;
+            }
+
+            let alias = Expr::Var(Var(flag(name.as_str())));
+            let pos = list.iter().position(|x| def_list.contains(x)).unwrap();
+            list.retain(|x| !def_list.contains(x));
+            if list.is_empty() {
+                *expr = alias;
+            } else {
+                list.insert(pos, alias);
+            }
+            return Some(name);
+        }
+        None
+    }
+}
+
+struct FoldAliases<'a>(&'a CfgAliases);
+
+impl VisitMut<Pred> for FoldAliases<'_> {
+    fn visit_mut_expr(&mut self, expr: &mut Expr) {
+        let aliases = self.0;
+        walk_mut_postorder(expr, &mut |expr| {
+            let mut folded = Vec::new();
+            while let Some(name) = aliases.fold_once(expr, &folded) {
+                folded.push(name);
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cfg::ast::{all, any, expr, not, target_os};
+
+    fn aliases() -> CfgAliases {
+        let mut a = CfgAliases::new();
+        a.define(
+            "linux_like",
+            any((target_os("linux"), target_os("android"))),
+        );
+        a.define("unix_like", any((flag("linux_like"), target_os("macos"))));
+        a
+    }
+
+    #[test]
+    fn expand() {
+        let a = aliases();
+        let mut x = expr(all((flag("unix_like"), not(flag("debug_assertions")))));
+        a.expand(&mut x).unwrap();
+
+        let expected = r#"all(any(any(target_os = "linux", target_os = "android"), target_os = "macos"), not(debug_assertions))"#;
+        assert_eq!(x.to_string(), expected);
+    }
+
+    #[test]
+    fn expand_cycle() {
+        let mut a = aliases();
+        a.define("linux_like", any((flag("unix_like"), target_os("linux"))));
+
+        let mut x = expr(flag("linux_like"));
+        let err = a.expand(&mut x).unwrap_err();
+        assert_eq!(err.cycle, ["linux_like", "unix_like", "linux_like"]);
+        assert_eq!(
+            err.to_string(),
+            "cyclic cfg alias: linux_like -> unix_like -> linux_like"
+        );
+    }
+
+    #[test]
+    fn fold() {
+        let a = aliases();
+
+        let mut x = expr(not(any((target_os("linux"), target_os("android")))));
+        a.fold(&mut x);
+        assert_eq!(x.to_string(), "not(linux_like)");
+
+        let mut x = expr(any((
+            target_os("macos"),
+            target_os("android"),
+            target_os("linux"),
+        )));
+        a.fold(&mut x);
+        assert_eq!(x.to_string(), "unix_like");
+
+        let mut x = expr(any((
+            target_os("android"),
+            target_os("linux"),
+            target_os("ios"),
+        )));
+        a.fold(&mut x);
+        assert_eq!(x.to_string(), r#"any(linux_like, target_os = "ios")"#);
+    }
+
+    #[test]
+    fn fold_cycle() {
+        let mut a = CfgAliases::new();
+        a.define("x", flag("y"));
+        a.define("y", flag("x"));
+        a.define("z", flag("z"));
+
+        let mut x = expr(any((flag("x"), flag("z"))));
+        a.fold(&mut x);
+        assert_eq!(x.to_string(), "any(x, z)");
+    }
+}
@@ -20,9 +20,17 @@ pub fn not(x: impl Into<Not<Pred>>) -> Not<Pred> {
     x.into()
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+/// A cfg predicate, `key` or `key = "value"`.
+///
+/// With the `serde` feature, a predicate is represented as `{"key": "unix"}` or `{"key": "target_os", "value": "linux"}`.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Pred {
     pub key: String,
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
     pub value: Option<String>,
 }
 
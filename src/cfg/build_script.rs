@@ -0,0 +1,202 @@
+#![allow(clippy::missing_errors_doc)]
+
+use super::aliases::CfgAliases;
+use super::ast::{Expr, Pred};
+use super::parsing::{self, parse_pred};
+
+use crate::eval::Env;
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::io;
+
+/// The cfg set of the compilation target, as seen by a build script.
+///
+/// Predicates are keyed by `(key, Option<value>)`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CfgSet {
+    preds: BTreeMap<String, BTreeSet<Option<String>>>,
+}
+
+impl CfgSet {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Reads the `CARGO_CFG_*` environment variables set by Cargo for build scripts.
+    #[must_use]
+    pub fn from_env() -> Self {
+        Self::from_vars(std::env::vars())
+    }
+
+    /// Collects the cfg set from `CARGO_CFG_*` variables.
+    ///
+    /// `CARGO_CFG_UNIX=""` becomes `unix` and `CARGO_CFG_TARGET_FEATURE="sse,sse2"`
+    /// becomes `target_feature = "sse"` and `target_feature = "sse2"`.
+    pub fn from_vars<K, V>(vars: impl IntoIterator<Item = (K, V)>) -> Self
+    where
+        K: AsRef<str>,
+        V: AsRef<str>,
+    {
+        let mut set = Self::new();
+        for (name, value) in vars {
+            let Some(key) = name.as_ref().strip_prefix("CARGO_CFG_") else {
+                continue;
+            };
+            let key = key.to_ascii_lowercase();
+            let value = value.as_ref();
+
+            if value.is_empty() {
+                set.insert(Pred { key, value: None });
+            } else {
+                for v in value.split(',') {
+                    set.insert(Pred {
+                        key: key.clone(),
+                        value: Some(v.to_owned()),
+                    });
+                }
+            }
+        }
+        set
+    }
+
+    /// Collects the cfg set from the arguments of `rustc --cfg`, or the lines of `rustc --print cfg`.
+    ///
+    /// Each argument is a single predicate such as `unix` or `feature="serde"`.
+    pub fn from_cfg_args<'a, I>(args: I) -> parsing::Result<'a, Self>
+    where
+        I: IntoIterator<Item = &'a str>,
+    {
+        let mut set = Self::new();
+        for arg in args {
+            set.insert(parse_pred(arg)?);
+        }
+        Ok(set)
+    }
+
+    pub fn insert(&mut self, pred: Pred) {
+        self.preds.entry(pred.key).or_default().insert(pred.value);
+    }
+
+    #[must_use]
+    pub fn contains(&self, pred: &Pred) -> bool {
+        self.contains_key_value(&pred.key, pred.value.as_deref())
+    }
+
+    #[must_use]
+    pub fn contains_key_value(&self, key: &str, value: Option<&str>) -> bool {
+        self.preds
+            .get(key)
+            .is_some_and(|values| values.contains(&value.map(str::to_owned)))
+    }
+
+    #[must_use]
+    pub fn eval(&self, expr: &Expr) -> bool {
+        expr.eval(self)
+    }
+}
+
+impl Env<Pred> for CfgSet {
+    fn lookup(&self, var: &Pred) -> bool {
+        self.contains(var)
+    }
+}
+
+/// Writes the `cargo::rustc-check-cfg` directive of every alias
+/// and the `cargo::rustc-cfg` directive of every alias which holds on `cfg`.
+///
+/// Aliases may refer to each other. A cyclic alias is reported as [`io::ErrorKind::InvalidInput`].
+pub fn write_aliases<W>(out: &mut W, aliases: &CfgAliases, cfg: &CfgSet) -> io::Result<()>
+where
+    W: io::Write + ?Sized,
+{
+    for (name, expr) in aliases.iter() {
+        let mut expr = expr.clone();
+        aliases
+            .expand(&mut expr)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+
+        writeln!(out, "cargo::rustc-check-cfg=cfg({name})")?;
+        if cfg.eval(&expr) {
+            writeln!(out, "cargo::rustc-cfg={name}")?;
+        }
+    }
+    Ok(())
+}
+
+/// Prints the directives of [`write_aliases`] for the current build script.
+///
+/// # Panics
+/// Panics if an alias is cyclic or writing to stdout fails.
+pub fn emit_aliases(aliases: &CfgAliases) {
+    let cfg = CfgSet::from_env();
+    write_aliases(&mut io::stdout().lock(), aliases, &cfg).unwrap();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cfg::ast::{all, any, expr, flag, key_value, not, target_os};
+
+    #[test]
+    fn from_vars() {
+        let vars = [
+            ("CARGO_CFG_UNIX", ""),
+            ("CARGO_CFG_TARGET_OS", "linux"),
+            ("CARGO_CFG_TARGET_FEATURE", "sse,sse2"),
+            ("CARGO_PKG_NAME", "demo"),
+        ];
+        let cfg = CfgSet::from_vars(vars);
+
+        assert!(cfg.contains(&flag("unix")));
+        assert!(cfg.contains(&target_os("linux")));
+        assert!(cfg.contains(&key_value("target_feature", "sse2")));
+        assert!(!cfg.contains(&flag("windows")));
+        assert!(!cfg.contains(&key_value("pkg_name", "demo")));
+    }
+
+    #[test]
+    fn from_cfg_args() {
+        let cfg = CfgSet::from_cfg_args(["unix", r#"feature="serde""#, r#"target_os = "linux""#])
+            .unwrap();
+
+        assert!(cfg.contains_key_value("feature", Some("serde")));
+        assert!(cfg.contains_key_value("unix", None));
+        assert!(!cfg.contains_key_value("unix", Some("")));
+        assert!(!cfg.contains_key_value("feature", None));
+
+        let x = expr(all((
+            flag("unix"),
+            key_value("feature", "serde"),
+            not(target_os("macos")),
+        )));
+        assert!(x.eval(&cfg));
+
+        assert!(CfgSet::from_cfg_args(["any(unix)"]).is_err());
+        assert!(CfgSet::from_cfg_args(["unix windows"]).is_err());
+    }
+
+    #[test]
+    fn directives() {
+        let cfg = CfgSet::from_vars([("CARGO_CFG_TARGET_OS", "android")]);
+        let mut aliases = CfgAliases::new();
+        aliases.define(
+            "linux_like",
+            any((target_os("linux"), target_os("android"))),
+        );
+        aliases.define("apple", any((target_os("macos"), target_os("ios"))));
+        aliases.define("unix_like", any((flag("linux_like"), flag("apple"))));
+
+        let mut out = Vec::new();
+        write_aliases(&mut out, &aliases, &cfg).unwrap();
+
+        let expected = "\
+cargo::rustc-check-cfg=cfg(linux_like)
+cargo::rustc-cfg=linux_like
+cargo::rustc-check-cfg=cfg(apple)
+cargo::rustc-check-cfg=cfg(unix_like)
+cargo::rustc-cfg=unix_like
+";
+        assert_eq!(String::from_utf8(out).unwrap(), expected);
+    }
+}
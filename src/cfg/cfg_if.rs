@@ -0,0 +1,80 @@
+use super::ast::{Expr, all, any, expr, not};
+
+use crate::transforms::simplify;
+
+/// Returns the mutually-exclusive guard of each branch, as `cfg_if!` expands them.
+///
+/// Branch `i` is guarded by `all(cond_i, not(any(cond_0, ..., cond_{i-1})))`.
+#[must_use]
+pub fn expand(conds: &[Expr]) -> Vec<Expr> {
+    (0..conds.len())
+        .map(|i| {
+            let prev = conds[..i].to_vec();
+            let mut guard = expr(all((conds[i].clone(), not(any(prev)))));
+            simplify(&mut guard);
+            guard
+        })
+        .collect()
+}
+
+/// Returns the guard of the trailing `else` branch, `not(any(cond_0, ..., cond_{n-1}))`.
+#[must_use]
+pub fn expand_else(conds: &[Expr]) -> Expr {
+    let mut guard = expr(not(any(conds.to_vec())));
+    simplify(&mut guard);
+    guard
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cfg::ast::{flag, target_os};
+    use crate::eval::eval_with;
+
+    #[test]
+    fn expand_branches() {
+        let conds = [
+            expr(flag("unix")),
+            expr(flag("windows")),
+            expr(target_os("wasi")),
+        ];
+        let guards = expand(&conds);
+
+        let expected = [
+            "unix",
+            "all(windows, not(unix))",
+            r#"all(target_os = "wasi", not(unix), not(windows))"#,
+        ];
+        let guards: Vec<_> = guards.iter().map(ToString::to_string).collect();
+        assert_eq!(guards, expected);
+
+        let guard = expand_else(&conds);
+        let expected = r#"all(not(unix), not(windows), not(target_os = "wasi"))"#;
+        assert_eq!(guard.to_string(), expected);
+    }
+
+    #[test]
+    fn mutually_exclusive() {
+        let conds = [
+            expr(any((flag("unix"), flag("windows")))),
+            expr(flag("unix")),
+            expr(all((flag("windows"), target_os("wasi")))),
+        ];
+        let mut guards = expand(&conds);
+        guards.push(expand_else(&conds));
+
+        assert_eq!(guards[1].to_string(), "false");
+
+        let preds = [flag("unix"), flag("windows"), target_os("wasi")];
+        for i in 0..8 {
+            let assign = |p: &_| {
+                preds
+                    .iter()
+                    .position(|x| x == p)
+                    .is_some_and(|x| (i >> x) & 1 == 1)
+            };
+            let hits = guards.iter().filter(|g| eval_with(g, &assign)).count();
+            assert_eq!(hits, 1, "i = {i}");
+        }
+    }
+}
@@ -0,0 +1,186 @@
+use super::ast::{Expr, Pred};
+
+use crate::eval::eval_with;
+
+/// Keys that take at most one value on any target.
+pub const SINGLE_VALUED_KEYS: &[&str] = &[
+    "target_arch",
+    "target_os",
+    "target_env",
+    "target_abi",
+    "target_vendor",
+    "target_pointer_width",
+    "target_endian",
+    "panic",
+];
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Report {
+    /// Targets matched by none of the branches.
+    pub uncovered: Vec<Vec<Pred>>,
+    /// Pairs of branches matched by the same target.
+    pub overlaps: Vec<Overlap>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Overlap {
+    pub first: usize,
+    pub second: usize,
+    pub witness: Vec<Pred>,
+}
+
+impl Report {
+    #[must_use]
+    pub fn is_ok(&self) -> bool {
+        self.uncovered.is_empty() && self.overlaps.is_empty()
+    }
+
+    fn record(&mut self, branches: &[Expr], target: &[Pred]) {
+        let hits: Vec<usize> = (0..branches.len())
+            .filter(|&i| eval_with(&branches[i], &|p| target.contains(p)))
+            .collect();
+
+        if hits.is_empty() {
+            self.uncovered.push(target.to_vec());
+        }
+
+        for (k, &first) in hits.iter().enumerate() {
+            for &second in &hits[k + 1..] {
+                if self
+                    .overlaps
+                    .iter()
+                    .any(|o| o.first == first && o.second == second)
+                {
+                    continue;
+                }
+                self.overlaps.push(Overlap {
+                    first,
+                    second,
+                    witness: target.to_vec(),
+                });
+            }
+        }
+    }
+
+    fn finish(mut self) -> Self {
+        self.overlaps.sort_by_key(|o| (o.first, o.second));
+        self
+    }
+}
+
+/// Checks that every assignment of the predicates matches exactly one branch.
+///
+/// A target is described by the predicates that hold on it.
+/// Predicates with a key in [`SINGLE_VALUED_KEYS`] are mutually exclusive,
+/// all other predicates are independent.
+///
+/// The number of assignments grows exponentially with the number of predicates.
+#[must_use]
+pub fn check(branches: &[Expr]) -> Report {
+    let groups = group_preds(branches);
+
+    let mut report = Report::default();
+    let mut choice = vec![0; groups.len()];
+    let mut target = Vec::with_capacity(groups.len());
+
+    loop {
+        target.clear();
+        for (group, &c) in groups.iter().zip(&choice) {
+            if c != 0 {
+                target.push(group[c - 1].clone());
+            }
+        }
+        report.record(branches, &target);
+
+        let Some(pos) = (0..groups.len()).find(|&g| choice[g] < groups[g].len()) else {
+            break;
+        };
+        choice[pos] += 1;
+        choice[..pos].fill(0);
+    }
+
+    report.finish()
+}
+
+/// Checks that every given target matches exactly one branch.
+#[must_use]
+pub fn check_targets(branches: &[Expr], targets: &[Vec<Pred>]) -> Report {
+    let mut report = Report::default();
+    for target in targets {
+        report.record(branches, target);
+    }
+    report.finish()
+}
+
+fn group_preds(branches: &[Expr]) -> Vec<Vec<Pred>> {
+    let mut groups: Vec<Vec<Pred>> = Vec::new();
+
+    for pred in branches.iter().flat_map(Expr::vars) {
+        let single_valued = pred.value.is_some() && SINGLE_VALUED_KEYS.contains(&pred.key.as_str());
+
+        if single_valued {
+            if let Some(group) = groups
+                .iter_mut()
+                .find(|g| g[0].value.is_some() && g[0].key == pred.key)
+            {
+                if !group.contains(pred) {
+                    group.push(pred.clone());
+                }
+                continue;
+            }
+        } else if groups.iter().any(|g| g.contains(pred)) {
+            continue;
+        }
+
+        groups.push(vec![pred.clone()]);
+    }
+
+    groups
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cfg::ast::{any, expr, flag, not, target_os};
+
+    #[test]
+    fn exhaustive() {
+        let branches = [
+            expr(target_os("linux")),
+            expr(target_os("macos")),
+            expr(not(any((target_os("linux"), target_os("macos"))))),
+        ];
+        let report = check(&branches);
+        assert!(report.is_ok(), "{report:?}");
+    }
+
+    #[test]
+    fn uncovered_and_overlap() {
+        let branches = [expr(flag("unix")), expr(target_os("linux"))];
+        let report = check(&branches);
+
+        assert_eq!(report.uncovered, [vec![]]);
+        assert_eq!(
+            report.overlaps,
+            [Overlap {
+                first: 0,
+                second: 1,
+                witness: vec![flag("unix"), target_os("linux")],
+            }]
+        );
+    }
+
+    #[test]
+    fn concrete_targets() {
+        let branches = [expr(flag("unix")), expr(target_os("windows"))];
+        let targets = [
+            vec![flag("unix"), target_os("linux")],
+            vec![flag("windows"), target_os("windows")],
+            vec![target_os("uefi")],
+        ];
+        let report = check_targets(&branches, &targets);
+
+        assert_eq!(report.uncovered, [vec![target_os("uefi")]]);
+        assert!(report.overlaps.is_empty());
+    }
+}
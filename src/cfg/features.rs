@@ -0,0 +1,244 @@
+use super::ast::{All, Any, Expr, Pred};
+
+use crate::eval::eval_with;
+use crate::transforms::simplify;
+use crate::visit_mut::{VisitMut, walk_mut_postorder};
+
+use std::collections::BTreeMap;
+
+/// Features enabling other features, as in a Cargo `[features]` table.
+///
+/// Only plain feature names are followed. Entries like `dep:foo` or `foo/bar` are kept but enable nothing.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FeatureGraph {
+    features: BTreeMap<String, Vec<String>>,
+}
+
+impl FeatureGraph {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert<I>(&mut self, feature: impl Into<String>, enables: I)
+    where
+        I: IntoIterator,
+        I::Item: Into<String>,
+    {
+        let list = self.features.entry(feature.into()).or_default();
+        list.extend(enables.into_iter().map(Into::into));
+    }
+
+    /// Returns the features enabled by `feature`, including itself.
+    #[must_use]
+    pub fn closure<'a>(&'a self, feature: &'a str) -> Vec<&'a str> {
+        let mut ans = vec![feature];
+        let mut i = 0;
+        while i < ans.len() {
+            if let Some(list) = self.features.get(ans[i]) {
+                for x in list {
+                    if !ans.contains(&x.as_str()) {
+                        ans.push(x);
+                    }
+                }
+            }
+            i += 1;
+        }
+        ans
+    }
+
+    /// Returns whether enabling `lhs` always enables `rhs`.
+    #[must_use]
+    pub fn implies(&self, lhs: &str, rhs: &str) -> bool {
+        self.closure(lhs).contains(&rhs)
+    }
+
+    /// Simplifies `expr` with the implications between features.
+    ///
+    /// `all(feature = "full", feature = "net")` becomes `feature = "full"` and
+    /// `any(feature = "full", feature = "net")` becomes `feature = "net"` when `full` enables `net`.
+    pub fn simplify(&self, expr: &mut Expr) {
+        loop {
+            let prev = expr.clone();
+
+            simplify(expr);
+            ImpliedFeatures(self).visit_mut_expr(expr);
+
+            let done = *expr == prev;
+            prev.drop_iterative();
+            if done {
+                break;
+            }
+        }
+    }
+
+    /// Returns the minimal sets of features whose closure satisfies `expr`.
+    ///
+    /// The candidates are the features enabling a feature of `expr`. Predicates other than `feature = ".."` are false.
+    /// The number of candidate sets grows exponentially with the number of candidates.
+    #[must_use]
+    pub fn minimal_feature_sets(&self, expr: &Expr) -> Vec<Vec<String>> {
+        let targets: Vec<&str> = expr.vars().into_iter().filter_map(as_feature).collect();
+        let mut candidates: Vec<&str> = self
+            .features
+            .keys()
+            .map(String::as_str)
+            .filter(|f| self.closure(f).iter().any(|x| targets.contains(x)))
+            .collect();
+        for name in &targets {
+            if !candidates.contains(name) {
+                candidates.push(name);
+            }
+        }
+        candidates.sort_unstable();
+
+        let n = candidates.len();
+        let mut found: Vec<Vec<&str>> = Vec::new();
+
+        for size in 0..=n {
+            let mut idx: Vec<usize> = (0..size).collect();
+            loop {
+                let set: Vec<&str> = idx.iter().map(|&i| candidates[i]).collect();
+
+                let is_superset = found.iter().any(|f| f.iter().all(|x| set.contains(x)));
+                if !is_superset {
+                    let enabled: Vec<&str> = set.iter().flat_map(|x| self.closure(x)).collect();
+                    let f = |p: &Pred| as_feature(p).is_some_and(|x| enabled.contains(&x));
+                    if eval_with(expr, &f) {
+                        found.push(set);
+                    }
+                }
+
+                let Some(i) = (0..size).rev().find(|&i| idx[i] != i + n - size) else {
+                    break;
+                };
+                idx[i] += 1;
+                for j in i + 1..size {
+                    idx[j] = idx[j - 1] + 1;
+                }
+            }
+        }
+
+        found
+            .into_iter()
+            .map(|set| set.into_iter().map(String::from).collect())
+            .collect()
+    }
+}
+
+fn as_feature(pred: &Pred) -> Option<&str> {
+    match (pred.key.as_str(), &pred.value) {
+        ("feature", Some(name)) => Some(name),
+        _ => None,
+    }
+}
+
+fn as_expr_feature(expr: &Expr) -> Option<&str> {
+    expr.as_var().and_then(|var| as_feature(&var.0))
+}
+
+/// Removes list items which are implied by (in `all`) or imply (in `any`) another item.
+fn remove_implied(list: &mut Vec<Expr>, f: impl Fn(&str, &str) -> bool) {
+    let mut i = 0;
+    while i < list.len() {
+        if let Some(x) = as_expr_feature(&list[i]) {
+            let redundant = (0..list.len())
+                .filter(|&j| j != i)
+                .filter_map(|j| as_expr_feature(&list[j]))
+                .any(|y| f(x, y));
+            if redundant {
+                list.remove(i);
+                continue;
+            }
+        }
+        i += 1;
+    }
+}
+
+struct ImpliedFeatures<'a>(&'a FeatureGraph);
+
+impl VisitMut<Pred> for ImpliedFeatures<'_> {
+    fn visit_mut_expr(&mut self, expr: &mut Expr) {
+        let graph = self.0;
+        walk_mut_postorder(expr, &mut |expr| match expr {
+            Expr::Any(Any(any)) => remove_implied(any, |x, y| graph.implies(x, y)),
+            Expr::All(All(all)) => remove_implied(all, |x, y| graph.implies(y, x)),
+            _ => {}
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cfg::ast::{all, any, expr, flag, key_value};
+
+    fn feature(s: &str) -> Pred {
+        key_value("feature", s)
+    }
+
+    fn graph() -> FeatureGraph {
+        let mut g = FeatureGraph::new();
+        g.insert("full", ["net", "fs"]);
+        g.insert("net", ["io"]);
+        g.insert("fs", ["io"]);
+        g.insert("io", [] as [&str; 0]);
+        g
+    }
+
+    #[test]
+    fn closure() {
+        let g = graph();
+        assert_eq!(g.closure("full"), ["full", "net", "fs", "io"]);
+        assert!(g.implies("full", "io"));
+        assert!(!g.implies("net", "fs"));
+    }
+
+    #[test]
+    fn simplify_implied() {
+        let g = graph();
+
+        let mut x = expr(all((feature("full"), feature("net"), flag("unix"))));
+        g.simplify(&mut x);
+        assert_eq!(x.to_string(), r#"all(feature = "full", unix)"#);
+
+        let mut x = expr(any((feature("full"), feature("net"))));
+        g.simplify(&mut x);
+        assert_eq!(x.to_string(), r#"feature = "net""#);
+    }
+
+    #[test]
+    fn simplify_cycle() {
+        let mut g = FeatureGraph::new();
+        g.insert("a", ["b"]);
+        g.insert("b", ["a"]);
+
+        let mut x = expr(all((feature("a"), feature("b"))));
+        g.simplify(&mut x);
+        assert_eq!(x.to_string(), r#"feature = "b""#);
+    }
+
+    #[test]
+    fn minimal_sets() {
+        let g = graph();
+
+        let x = expr(all((feature("net"), feature("fs"))));
+        let sets = g.minimal_feature_sets(&x);
+        assert_eq!(sets, [vec!["full"], vec!["fs", "net"]]);
+
+        let x = expr(any((feature("net"), feature("extra"))));
+        let sets = g.minimal_feature_sets(&x);
+        assert_eq!(sets, [vec!["extra"], vec!["full"], vec!["net"]]);
+    }
+
+    #[test]
+    fn minimal_sets_ignore_unrelated() {
+        let mut g = graph();
+        for i in 0..64 {
+            g.insert(format!("unrelated{i}"), [format!("unrelated{}", i + 1)]);
+        }
+
+        let sets = g.minimal_feature_sets(&expr(feature("net")));
+        assert_eq!(sets, [vec!["full"], vec!["net"]]);
+    }
+}
@@ -1,2 +1,15 @@
+pub mod aliases;
 pub mod ast;
+pub mod build_script;
+pub mod cfg_if;
+pub mod check;
+pub mod features;
 pub mod parsing;
+
+#[cfg(feature = "serde")]
+#[cfg_attr(docsrs, doc(cfg(feature = "serde")))]
+pub mod serde_str;
+
+#[cfg(feature = "proc-macro2")]
+#[cfg_attr(docsrs, doc(cfg(feature = "proc-macro2")))]
+pub mod tokens;
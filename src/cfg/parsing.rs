@@ -2,89 +2,46 @@
 
 use super::ast::{All, Any, Expr, Not, Pred, all, any, expr, not};
 
-use std::backtrace::Backtrace;
-use std::ops::Not as _;
-use std::panic::Location;
+use crate::parsing::{consume_tag, ensure, parse_identifier, skip_space0, skip_tag, take_while1};
 
-#[derive(Debug)]
-pub struct Error<'p> {
-    pub input: &'p str,
-    pub location: &'static Location<'static>,
-    pub backtrace: Backtrace,
-}
+use std::ops::Not as _;
 
-pub type Result<'p, T, E = Error<'p>> = std::result::Result<T, E>;
-
-macro_rules! ensure {
-    ($s:expr, $cond:expr) => {
-        if !$cond {
-            return Err(Error {
-                input: $s,
-                location: Location::caller(),
-                backtrace: Backtrace::capture(),
-            });
-        }
-    };
-}
+pub use crate::parsing::{Error, MAX_DEPTH, Result};
 
 pub fn parse(mut s: &str) -> Result<'_, Expr> {
     let s = &mut s;
     skip_space0(s);
-    let expr = parse_expr(s)?;
+    let expr = parse_expr(s, 0)?;
     skip_space0(s);
     ensure!(s, s.is_empty());
     Ok(expr)
 }
 
-fn take_while1<'p>(s: &mut &'p str, f: impl Fn(char) -> bool) -> Result<'p, &'p str> {
-    let end = s
-        .char_indices()
-        .find_map(|(i, c)| f(c).not().then_some(i))
-        .unwrap_or(s.len());
-
-    ensure!(s, end > 0);
-
-    let (ans, rest) = s.split_at(end);
-    *s = rest;
-    Ok(ans)
-}
-
-fn consume_tag<'p>(s: &mut &'p str, tag: &str) -> Result<'p, &'p str> {
-    ensure!(s, s.starts_with(tag));
-    let (ans, rest) = s.split_at(tag.len());
-    *s = rest;
-    Ok(ans)
-}
-
-fn skip_space0(s: &mut &str) {
-    *s = s.trim_start();
-}
-
-fn skip_tag<'p>(s: &mut &'p str, tag: &str) -> Option<&'p str> {
-    if s.starts_with(tag) {
-        let (ans, rest) = s.split_at(tag.len());
-        *s = rest;
-        Some(ans)
-    } else {
-        None
-    }
+/// Parses a single predicate, as passed to `rustc --cfg` or printed by `rustc --print cfg`.
+pub fn parse_pred(mut s: &str) -> Result<'_, Pred> {
+    let s = &mut s;
+    skip_space0(s);
+    let pred = parse_key_value(s)?;
+    skip_space0(s);
+    ensure!(s, s.is_empty());
+    Ok(pred)
 }
 
 // https://doc.rust-lang.org/reference/conditional-compilation.html
 
-fn parse_expr<'p>(s: &mut &'p str) -> Result<'p, Expr> {
+fn parse_expr<'p>(s: &mut &'p str, depth: usize) -> Result<'p, Expr> {
     if s.starts_with("any") {
-        Ok(expr(parse_any(s)?))
+        Ok(expr(parse_any(s, depth)?))
     } else if s.starts_with("all") {
-        Ok(expr(parse_all(s)?))
+        Ok(expr(parse_all(s, depth)?))
     } else if s.starts_with("not") {
-        Ok(expr(parse_not(s)?))
+        Ok(expr(parse_not(s, depth)?))
     } else {
-        Ok(expr(parse_pred(s)?))
+        Ok(expr(parse_key_value(s)?))
     }
 }
 
-fn parse_pred<'p>(s: &mut &'p str) -> Result<'p, Pred> {
+fn parse_key_value<'p>(s: &mut &'p str) -> Result<'p, Pred> {
     let key = parse_identifier(s)?;
 
     let has_value = s.trim_start().starts_with('=');
@@ -104,60 +61,58 @@ fn parse_pred<'p>(s: &mut &'p str) -> Result<'p, Pred> {
     })
 }
 
-// TODO: unicode identifier
-fn parse_identifier<'p>(s: &mut &'p str) -> Result<'p, &'p str> {
-    ensure!(s, s.starts_with(|c| matches!(c, 'a'..='z'|'A'..='Z'|'_')));
-    take_while1(s, |c| matches!(c, 'a'..='z'|'A'..='Z'|'0'..='9'|'_'))
-}
-
 // TODO: escaped string
 fn parse_string_literal<'p>(s: &mut &'p str) -> Result<'p, &'p str> {
     consume_tag(s, "\"")?;
 
+    let start = *s;
     let ans = take_while1(s, |c| c != '"')?;
-    assert!(ans.contains('\\').not());
+    ensure!(start, ans.contains('\\').not());
 
     consume_tag(s, "\"")?;
 
     Ok(ans)
 }
 
-fn parse_any<'p>(s: &mut &'p str) -> Result<'p, Any<Pred>> {
+fn parse_any<'p>(s: &mut &'p str, depth: usize) -> Result<'p, Any<Pred>> {
+    ensure!(s, depth < MAX_DEPTH);
     consume_tag(s, "any")?;
     skip_space0(s);
     consume_tag(s, "(")?;
-    let list = parse_expr_list(s)?;
+    let list = parse_expr_list(s, depth + 1)?;
     skip_space0(s);
     consume_tag(s, ")")?;
     Ok(any(list))
 }
 
-fn parse_all<'p>(s: &mut &'p str) -> Result<'p, All<Pred>> {
+fn parse_all<'p>(s: &mut &'p str, depth: usize) -> Result<'p, All<Pred>> {
+    ensure!(s, depth < MAX_DEPTH);
     consume_tag(s, "all")?;
     skip_space0(s);
     consume_tag(s, "(")?;
-    let list = parse_expr_list(s)?;
+    let list = parse_expr_list(s, depth + 1)?;
     skip_space0(s);
     consume_tag(s, ")")?;
     Ok(all(list))
 }
 
-fn parse_not<'p>(s: &mut &'p str) -> Result<'p, Not<Pred>> {
+fn parse_not<'p>(s: &mut &'p str, depth: usize) -> Result<'p, Not<Pred>> {
+    ensure!(s, depth < MAX_DEPTH);
     consume_tag(s, "not")?;
     skip_space0(s);
     consume_tag(s, "(")?;
-    let expr = parse_expr(s)?;
+    let expr = parse_expr(s, depth + 1)?;
     skip_space0(s);
     consume_tag(s, ")")?;
     Ok(not(expr))
 }
 
-fn parse_expr_list<'p>(s: &mut &'p str) -> Result<'p, Vec<Expr>> {
+fn parse_expr_list<'p>(s: &mut &'p str, depth: usize) -> Result<'p, Vec<Expr>> {
     let mut ans: Vec<Expr> = Vec::new();
 
     while s.starts_with(')').not() {
         skip_space0(s);
-        ans.push(parse_expr(s)?);
+        ans.push(parse_expr(s, depth)?);
         skip_space0(s);
         skip_tag(s, ",");
     }
@@ -176,4 +131,18 @@ mod tests {
         let expr = parse(input).unwrap();
         assert_eq!(expr.to_string(), input.trim());
     }
+
+    #[test]
+    fn depth_limit() {
+        let nested = |n| format!("{}unix{}", "not(".repeat(n), ")".repeat(n));
+        assert!(parse(&nested(MAX_DEPTH)).is_ok());
+        assert!(parse(&nested(MAX_DEPTH + 1)).is_err());
+        assert!(parse(&nested(1_000_000)).is_err());
+    }
+
+    #[test]
+    fn escaped_string() {
+        assert!(parse(r#"target_os = "a\\b""#).is_err());
+        assert!(parse_pred(r#"feature = "a\"b""#).is_err());
+    }
 }
@@ -0,0 +1,68 @@
+//! Serializes a cfg expression as its cfg string.
+//!
+//! ```ignore
+//! #[derive(Serialize, Deserialize)]
+//! struct Config {
+//!     #[serde(with = "bool_logic::cfg::serde_str")]
+//!     cond: bool_logic::cfg::ast::Expr,
+//! }
+//! ```
+
+#![allow(clippy::missing_errors_doc)]
+
+use super::ast::Expr;
+use super::parsing::parse;
+
+use serde::de::{self, Deserialize, Deserializer};
+use serde::ser::Serializer;
+
+pub fn serialize<S>(expr: &Expr, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    serializer.collect_str(expr)
+}
+
+pub fn deserialize<'de, D>(deserializer: D) -> Result<Expr, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let s = String::deserialize(deserializer)?;
+    parse(&s)
+        .map_err(|err| de::Error::custom(format_args!("invalid cfg expression at {:?}", err.input)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cfg::ast::{all, expr, flag, not, target_os};
+
+    #[derive(Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+    struct Config {
+        #[serde(with = "super")]
+        cond: Expr,
+    }
+
+    #[test]
+    fn cfg_string() {
+        let config = Config {
+            cond: expr(all((flag("unix"), not(target_os("macos"))))),
+        };
+        let json = serde_json::to_string(&config).unwrap();
+        assert_eq!(json, r#"{"cond":"all(unix, not(target_os = \"macos\"))"}"#);
+        assert_eq!(serde_json::from_str::<Config>(&json).unwrap(), config);
+
+        let err = serde_json::from_str::<Config>(r#"{"cond":"all(unix"}"#).unwrap_err();
+        assert!(
+            err.to_string()
+                .starts_with("invalid cfg expression at \"\""),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn escaped_string() {
+        let json = r#"{"cond":"x = \"a\\\\b\""}"#;
+        assert!(serde_json::from_str::<Config>(json).is_err());
+    }
+}
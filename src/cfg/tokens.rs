@@ -0,0 +1,353 @@
+//! Conversion between cfg expressions and `proc_macro2` token streams.
+
+#![allow(clippy::missing_errors_doc)]
+
+use super::ast::{Expr, Pred};
+
+use crate::ast::{self, All, Any, Not, Var};
+use crate::parsing::{MAX_DEPTH, parse_identifier};
+
+use std::error::Error as StdError;
+use std::fmt;
+
+use proc_macro2::{Delimiter, Group, Ident, Literal, Punct, Spacing, Span, TokenStream, TokenTree};
+
+/// A predicate with the span it was parsed from.
+///
+/// Equality ignores the span, so expressions of spanned predicates can be simplified directly.
+#[derive(Debug, Clone)]
+pub struct SpannedPred {
+    pub pred: Pred,
+    pub span: Span,
+}
+
+impl PartialEq for SpannedPred {
+    fn eq(&self, other: &Self) -> bool {
+        self.pred == other.pred
+    }
+}
+
+impl Eq for SpannedPred {}
+
+impl fmt::Display for SpannedPred {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.pred.fmt(f)
+    }
+}
+
+pub type SpannedExpr = ast::Expr<SpannedPred>;
+
+#[derive(Debug, Clone)]
+pub struct Error {
+    pub span: Span,
+    pub message: String,
+}
+
+impl Error {
+    fn new(span: Span, message: impl Into<String>) -> Self {
+        Self {
+            span,
+            message: message.into(),
+        }
+    }
+
+    /// Returns `::core::compile_error!("..")` at the span of the error.
+    #[must_use]
+    pub fn to_compile_error(&self) -> TokenStream {
+        let tokens = [
+            TokenTree::Punct(Punct::new(':', Spacing::Joint)),
+            TokenTree::Punct(Punct::new(':', Spacing::Alone)),
+            TokenTree::Ident(Ident::new("core", self.span)),
+            TokenTree::Punct(Punct::new(':', Spacing::Joint)),
+            TokenTree::Punct(Punct::new(':', Spacing::Alone)),
+            TokenTree::Ident(Ident::new("compile_error", self.span)),
+            TokenTree::Punct(Punct::new('!', Spacing::Alone)),
+            TokenTree::Group(Group::new(
+                Delimiter::Parenthesis,
+                TokenStream::from(TokenTree::Literal(Literal::string(&self.message))),
+            )),
+        ];
+        tokens
+            .into_iter()
+            .map(|mut tt| {
+                tt.set_span(self.span);
+                tt
+            })
+            .collect()
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl StdError for Error {}
+
+/// Parses the arguments of a `#[cfg(..)]` attribute.
+pub fn parse(tokens: TokenStream) -> Result<SpannedExpr, Error> {
+    let mut iter = tokens.into_iter().peekable();
+    let expr = parse_expr(&mut iter, Span::call_site(), 0)?;
+    if let Some(tt) = iter.next() {
+        return Err(Error::new(
+            tt.span(),
+            "unexpected token after cfg predicate",
+        ));
+    }
+    Ok(expr)
+}
+
+type Iter = std::iter::Peekable<proc_macro2::token_stream::IntoIter>;
+
+fn parse_expr(iter: &mut Iter, end: Span, depth: usize) -> Result<SpannedExpr, Error> {
+    let ident = match iter.next() {
+        Some(TokenTree::Ident(ident)) => ident,
+        Some(tt) => return Err(Error::new(tt.span(), "expected identifier")),
+        None => return Err(Error::new(end, "expected cfg predicate")),
+    };
+    let name = ident.to_string();
+    let span = ident.span();
+
+    let is_list = matches!(name.as_str(), "any" | "all" | "not");
+    let is_paren = |tt: &TokenTree| match tt {
+        TokenTree::Group(g) => g.delimiter() == Delimiter::Parenthesis,
+        _ => false,
+    };
+    if let Some(TokenTree::Group(group)) = iter.next_if(|tt| is_list && is_paren(tt)) {
+        if depth >= MAX_DEPTH {
+            return Err(Error::new(span, "cfg predicate is nested too deeply"));
+        }
+        let list = parse_expr_list(group.stream(), group.span_close(), depth + 1)?;
+        return match name.as_str() {
+            "any" => Ok(ast::Expr::Any(Any(list))),
+            "all" => Ok(ast::Expr::All(All(list))),
+            _ => match <[_; 1]>::try_from(list) {
+                Ok([x]) => Ok(ast::Expr::Not(Not(Box::new(x)))),
+                Err(_) => Err(Error::new(
+                    group.span(),
+                    "`not` takes exactly one cfg predicate",
+                )),
+            },
+        };
+    }
+
+    match name.as_str() {
+        "true" => return Ok(ast::Expr::Const(true)),
+        "false" => return Ok(ast::Expr::Const(false)),
+        _ => {}
+    }
+
+    let value = match iter.peek() {
+        Some(TokenTree::Punct(p)) if p.as_char() == '=' => {
+            let eq_span = p.span();
+            iter.next();
+            match iter.next() {
+                Some(TokenTree::Literal(lit)) => Some(parse_string_literal(&lit)?),
+                Some(tt) => return Err(Error::new(tt.span(), "expected string literal")),
+                None => return Err(Error::new(eq_span, "expected string literal after `=`")),
+            }
+        }
+        _ => None,
+    };
+
+    let key = name.strip_prefix("r#").unwrap_or(&name).to_owned();
+    Ok(ast::Expr::Var(Var(SpannedPred {
+        pred: Pred { key, value },
+        span,
+    })))
+}
+
+fn parse_expr_list(
+    tokens: TokenStream,
+    end: Span,
+    depth: usize,
+) -> Result<Vec<SpannedExpr>, Error> {
+    let mut iter = tokens.into_iter().peekable();
+    let mut list = Vec::new();
+    while iter.peek().is_some() {
+        list.push(parse_expr(&mut iter, end, depth)?);
+        match iter.next() {
+            Some(TokenTree::Punct(p)) if p.as_char() == ',' => {}
+            Some(tt) => return Err(Error::new(tt.span(), "expected `,`")),
+            None => break,
+        }
+    }
+    Ok(list)
+}
+
+fn parse_string_literal(lit: &Literal) -> Result<String, Error> {
+    let repr = lit.to_string();
+    let raw = repr.strip_prefix('r').map(|s| s.trim_matches('#'));
+    let inner = raw
+        .unwrap_or(&repr)
+        .strip_prefix('"')
+        .and_then(|s| s.strip_suffix('"'))
+        .ok_or_else(|| Error::new(lit.span(), "expected string literal"))?;
+
+    if raw.is_none() && inner.contains('\\') {
+        return Err(Error::new(
+            lit.span(),
+            "escaped string literals are not supported",
+        ));
+    }
+    Ok(inner.to_owned())
+}
+
+/// Converts a cfg expression into the arguments of a `#[cfg(..)]` attribute, at the call site.
+///
+/// Fails if a predicate key is not an identifier.
+pub fn to_tokens(expr: &Expr) -> Result<TokenStream, Error> {
+    let mut out = TokenStream::new();
+    write_tokens(&mut out, expr, &|p| (p, Span::call_site()))?;
+    Ok(out)
+}
+
+/// Converts a spanned cfg expression into tokens, keeping the span of each predicate.
+///
+/// Fails if a predicate key is not an identifier.
+pub fn to_tokens_spanned(expr: &SpannedExpr) -> Result<TokenStream, Error> {
+    let mut out = TokenStream::new();
+    write_tokens(&mut out, expr, &|p| (&p.pred, p.span))?;
+    Ok(out)
+}
+
+fn is_identifier(s: &str) -> bool {
+    let mut rest = s;
+    parse_identifier(&mut rest).is_ok() && rest.is_empty()
+}
+
+fn write_tokens<T>(
+    out: &mut TokenStream,
+    expr: &ast::Expr<T>,
+    f: &dyn Fn(&T) -> (&Pred, Span),
+) -> Result<(), Error> {
+    let call_site = Span::call_site();
+    let (name, list) = match expr {
+        ast::Expr::Any(Any(list)) => ("any", list.as_slice()),
+        ast::Expr::All(All(list)) => ("all", list.as_slice()),
+        ast::Expr::Not(Not(not)) => ("not", std::slice::from_ref(&**not)),
+        ast::Expr::Var(Var(x)) => {
+            let (pred, span) = f(x);
+            if !is_identifier(&pred.key) {
+                return Err(Error::new(
+                    span,
+                    format!("cfg key `{}` is not an identifier", pred.key),
+                ));
+            }
+            out.extend([TokenTree::Ident(Ident::new(&pred.key, span))]);
+            if let Some(value) = &pred.value {
+                let mut eq = Punct::new('=', Spacing::Alone);
+                eq.set_span(span);
+                let mut lit = Literal::string(value);
+                lit.set_span(span);
+                out.extend([TokenTree::Punct(eq), TokenTree::Literal(lit)]);
+            }
+            return Ok(());
+        }
+        ast::Expr::Const(b) => {
+            out.extend([TokenTree::Ident(Ident::new(
+                if *b { "true" } else { "false" },
+                call_site,
+            ))]);
+            return Ok(());
+        }
+    };
+
+    let mut inner = TokenStream::new();
+    for (i, x) in list.iter().enumerate() {
+        if i != 0 {
+            inner.extend([TokenTree::Punct(Punct::new(',', Spacing::Alone))]);
+        }
+        write_tokens(&mut inner, x, f)?;
+    }
+    out.extend([
+        TokenTree::Ident(Ident::new(name, call_site)),
+        TokenTree::Group(Group::new(Delimiter::Parenthesis, inner)),
+    ]);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cfg::ast::{all, any, expr, flag, not, target_os};
+    use crate::transforms::simplify;
+
+    fn tokens(s: &str) -> TokenStream {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn parse_tokens() {
+        let x = parse(tokens(
+            r#"all(unix, not(any(target_os = "linux", target_os = r"macos")),)"#,
+        ))
+        .unwrap();
+        let x = x.map(&mut |p| p.pred);
+        let expected = expr(all((
+            flag("unix"),
+            not(any((target_os("linux"), target_os("macos")))),
+        )));
+        assert_eq!(x, expected);
+    }
+
+    #[test]
+    fn round_trip() {
+        let x = expr(all((
+            flag("unix"),
+            not(any((target_os("linux"), target_os("macos")))),
+        )));
+        let ts = to_tokens(&x).unwrap();
+        assert_eq!(
+            ts.to_string(),
+            r#"all (unix , not (any (target_os = "linux" , target_os = "macos")))"#
+        );
+        assert_eq!(parse(ts).unwrap().map(&mut |p| p.pred), x);
+    }
+
+    #[test]
+    fn simplify_spanned() {
+        let mut x = parse(tokens("all(unix, not(not(windows)), unix)")).unwrap();
+        simplify(&mut x);
+        assert_eq!(
+            to_tokens_spanned(&x).unwrap().to_string(),
+            "all (unix , windows)"
+        );
+    }
+
+    #[test]
+    fn errors() {
+        let err = |s| parse(tokens(s)).unwrap_err().message;
+        assert_eq!(err("not(a, b)"), "`not` takes exactly one cfg predicate");
+        assert_eq!(err("target_os = 1"), "expected string literal");
+        assert_eq!(err("target_os ="), "expected string literal after `=`");
+        assert_eq!(
+            err(r#"target_os = "a\nb""#),
+            "escaped string literals are not supported"
+        );
+        assert_eq!(err("any(a b)"), "expected `,`");
+        assert_eq!(err("a b"), "unexpected token after cfg predicate");
+        assert_eq!(err(""), "expected cfg predicate");
+
+        let nested = |n| tokens(&format!("{}unix{}", "not(".repeat(n), ")".repeat(n)));
+        assert!(parse(nested(MAX_DEPTH)).is_ok());
+        assert_eq!(
+            parse(nested(MAX_DEPTH + 1)).unwrap_err().message,
+            "cfg predicate is nested too deeply"
+        );
+
+        let e = parse(tokens("\"x\"")).unwrap_err();
+        assert_eq!(
+            e.to_compile_error().to_string(),
+            r#":: core :: compile_error ! ("expected identifier")"#
+        );
+    }
+
+    #[test]
+    fn invalid_key() {
+        let x = expr(any((flag("unix"), flag("foo-bar"))));
+        let err = to_tokens(&x).unwrap_err();
+        assert_eq!(err.message, "cfg key `foo-bar` is not an identifier");
+    }
+}
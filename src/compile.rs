@@ -0,0 +1,204 @@
+//! Flat bytecode for evaluating an expression repeatedly.
+
+use crate::ast::{All, Any, Expr, Not, Var};
+
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::slice;
+
+/// An instruction operating on a single boolean accumulator.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Inst {
+    /// Loads the value of a variable.
+    Load(usize),
+    /// Loads a constant.
+    Const(bool),
+    /// Negates the accumulator.
+    Not,
+    /// Jumps to the target if the accumulator is true.
+    JumpIfTrue(usize),
+    /// Jumps to the target if the accumulator is false.
+    JumpIfFalse(usize),
+}
+
+/// An expression compiled by [`Expr::compile`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompiledExpr<'a, T> {
+    vars: Vec<&'a T>,
+    code: Vec<Inst>,
+}
+
+impl<T> Expr<T>
+where
+    T: Eq + Hash,
+{
+    /// Lowers the expression into a flat instruction list with short-circuit jumps,
+    /// numbering the variables in first-occurrence order.
+    #[must_use]
+    pub fn compile(&self) -> CompiledExpr<'_, T> {
+        enum Frame<'a, T> {
+            List {
+                any: bool,
+                rest: slice::Iter<'a, Expr<T>>,
+                jumps: Vec<usize>,
+            },
+            Not,
+        }
+
+        let jump = |any, target| {
+            if any {
+                Inst::JumpIfTrue(target)
+            } else {
+                Inst::JumpIfFalse(target)
+            }
+        };
+
+        let vars = self.vars();
+        let index: HashMap<&T, usize> = vars.iter().enumerate().map(|(i, &v)| (v, i)).collect();
+        let mut code = Vec::new();
+        let mut stack: Vec<Frame<'_, T>> = Vec::new();
+        let mut cur = self;
+        'descend: loop {
+            loop {
+                match cur {
+                    Expr::Any(Any(list)) | Expr::All(All(list)) => {
+                        let any = cur.is_any();
+                        let mut rest = list.iter();
+                        let Some(first) = rest.next() else {
+                            code.push(Inst::Const(!any));
+                            break;
+                        };
+                        stack.push(Frame::List {
+                            any,
+                            rest,
+                            jumps: Vec::with_capacity(list.len() - 1),
+                        });
+                        cur = first;
+                    }
+                    Expr::Not(Not(not)) => {
+                        stack.push(Frame::Not);
+                        cur = not;
+                    }
+                    Expr::Var(Var(x)) => {
+                        code.push(Inst::Load(index[x]));
+                        break;
+                    }
+                    Expr::Const(b) => {
+                        code.push(Inst::Const(*b));
+                        break;
+                    }
+                }
+            }
+
+            loop {
+                match stack.last_mut() {
+                    None => break 'descend,
+                    Some(Frame::Not) => code.push(Inst::Not),
+                    Some(Frame::List { any, rest, jumps }) => {
+                        if let Some(next) = rest.next() {
+                            jumps.push(code.len());
+                            code.push(jump(*any, 0));
+                            cur = next;
+                            continue 'descend;
+                        }
+                        let end = code.len();
+                        for &j in &*jumps {
+                            code[j] = jump(*any, end);
+                        }
+                    }
+                }
+                stack.pop();
+            }
+        }
+        CompiledExpr { vars, code }
+    }
+}
+
+impl<'a, T> CompiledExpr<'a, T> {
+    /// Returns the variables in the order of the values passed to [`eval`](Self::eval).
+    #[must_use]
+    pub fn vars(&self) -> &[&'a T] {
+        &self.vars
+    }
+
+    #[must_use]
+    pub fn code(&self) -> &[Inst] {
+        &self.code
+    }
+
+    /// Evaluates the expression with `values[i]` as the value of `self.vars()[i]`.
+    ///
+    /// # Panics
+    /// Panics if `values` is shorter than [`vars`](Self::vars).
+    #[must_use]
+    pub fn eval(&self, values: &[bool]) -> bool {
+        let mut acc = false;
+        let mut pc = 0;
+        while let Some(inst) = self.code.get(pc) {
+            pc += 1;
+            match *inst {
+                Inst::Load(i) => acc = values[i],
+                Inst::Const(b) => acc = b,
+                Inst::Not => acc = !acc,
+                Inst::JumpIfTrue(target) if acc => pc = target,
+                Inst::JumpIfFalse(target) if !acc => pc = target,
+                Inst::JumpIfTrue(_) | Inst::JumpIfFalse(_) => {}
+            }
+        }
+        acc
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::{all, any, const_, expr, not, var};
+    use crate::eval::eval_with;
+
+    #[test]
+    fn code() {
+        let x = expr(any((var("a"), all((not(var("b")), var("a"))))));
+        let c = x.compile();
+        assert_eq!(c.vars(), [&"a", &"b"]);
+        assert_eq!(
+            c.code(),
+            [
+                Inst::Load(0),
+                Inst::JumpIfTrue(6),
+                Inst::Load(1),
+                Inst::Not,
+                Inst::JumpIfFalse(6),
+                Inst::Load(0),
+            ]
+        );
+    }
+
+    #[test]
+    fn matches_eval_with() {
+        let x = expr(all((
+            any((var(0), not(var(1)), all(()))),
+            not(any((all((var(2), var(0))), any(())))),
+            any((var(3), const_(false))),
+        )));
+        let c = x.compile();
+        assert_eq!(c.vars(), [&0, &1, &2, &3]);
+        for i in 0..16 {
+            let values: Vec<bool> = (0..4).map(|j| (i >> j) & 1 == 1).collect();
+            let expected = eval_with(&x, &|&v| values[v]);
+            assert_eq!(c.eval(&values), expected, "i = {i}");
+        }
+    }
+
+    #[test]
+    fn deeply_nested() {
+        let mut x = expr(var(0));
+        for _ in 0..100_000 {
+            x = expr(all((not(x), const_(true))));
+        }
+        let c = x.compile();
+        assert_eq!(c.code().len(), 3 * 100_000 + 1);
+        assert!(c.eval(&[true]));
+        assert!(!c.eval(&[false]));
+        x.drop_iterative();
+    }
+}
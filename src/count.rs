@@ -0,0 +1,254 @@
+//! Model counting and probability of truth, by compiling to a binary decision diagram.
+
+use crate::ast::{Expr, Node, fold_ref};
+use crate::utils::index_vars;
+
+use std::collections::HashMap;
+use std::hash::Hash;
+
+const FALSE: usize = 0;
+const TRUE: usize = 1;
+
+/// A reduced ordered binary decision diagram over the variables `0..n`, in increasing order.
+///
+/// Nodes are created after their children, so iterating over ids visits children first.
+struct Bdd {
+    /// The variable, low child and high child of every node. The terminals have the variable `n`.
+    nodes: Vec<(usize, usize, usize)>,
+    unique: HashMap<(usize, usize, usize), usize>,
+    cache: HashMap<(bool, usize, usize), usize>,
+}
+
+impl Bdd {
+    /// Compiles `expr`, whose variables are in `0..n`, and returns the diagram with its root.
+    fn new(expr: &Expr<usize>, n: usize) -> (Self, usize) {
+        let mut bdd = Self {
+            nodes: vec![(n, FALSE, FALSE), (n, TRUE, TRUE)],
+            unique: HashMap::new(),
+            cache: HashMap::new(),
+        };
+        // Builds the diagrams of every sub-expression and of its negation, so that `not` is a swap.
+        let (root, _) = fold_ref(expr, &mut |node| match node {
+            Node::Any(list) => bdd.apply_list(false, &list),
+            Node::All(list) => bdd.apply_list(true, &list),
+            Node::Not((pos, neg)) => (neg, pos),
+            Node::Var(&v) => (bdd.make(v, FALSE, TRUE), bdd.make(v, TRUE, FALSE)),
+            Node::Const(b) => (usize::from(b), usize::from(!b)),
+        });
+        (bdd, root)
+    }
+
+    fn level(&self, u: usize) -> usize {
+        self.nodes[u].0
+    }
+
+    fn make(&mut self, var: usize, lo: usize, hi: usize) -> usize {
+        if lo == hi {
+            return lo;
+        }
+        let next = self.nodes.len();
+        let id = *self.unique.entry((var, lo, hi)).or_insert(next);
+        if id == next {
+            self.nodes.push((var, lo, hi));
+        }
+        id
+    }
+
+    /// Returns the conjunction (or disjunction) of the positive diagrams,
+    /// and the disjunction (or conjunction) of the negative ones.
+    fn apply_list(&mut self, is_and: bool, list: &[(usize, usize)]) -> (usize, usize) {
+        let (mut pos, mut neg) = (usize::from(is_and), usize::from(!is_and));
+        // Variables are numbered in order of first occurrence, so later items tend to be deeper.
+        for &(p, n) in list.iter().rev() {
+            pos = self.apply(is_and, pos, p);
+            neg = self.apply(!is_and, neg, n);
+        }
+        (pos, neg)
+    }
+
+    /// Returns the conjunction (or disjunction) of two diagrams, with an explicit stack.
+    fn apply(&mut self, is_and: bool, a: usize, b: usize) -> usize {
+        enum Task {
+            Apply(usize, usize),
+            Make(usize, (usize, usize)),
+        }
+
+        let mut tasks = vec![Task::Apply(a, b)];
+        let mut results = Vec::new();
+        while let Some(task) = tasks.pop() {
+            match task {
+                Task::Apply(a, b) => {
+                    let (a, b) = (a.min(b), a.max(b));
+                    let terminal = match a {
+                        _ if a == b => Some(a),
+                        FALSE => Some(if is_and { FALSE } else { b }),
+                        TRUE => Some(if is_and { b } else { TRUE }),
+                        _ => self.cache.get(&(is_and, a, b)).copied(),
+                    };
+                    if let Some(r) = terminal {
+                        results.push(r);
+                        continue;
+                    }
+                    let v = self.level(a).min(self.level(b));
+                    let (a_lo, a_hi) = self.cofactors(a, v);
+                    let (b_lo, b_hi) = self.cofactors(b, v);
+                    tasks.push(Task::Make(v, (a, b)));
+                    tasks.push(Task::Apply(a_hi, b_hi));
+                    tasks.push(Task::Apply(a_lo, b_lo));
+                }
+                Task::Make(v, (a, b)) => {
+                    let hi = results.pop().unwrap();
+                    let lo = results.pop().unwrap();
+                    let r = self.make(v, lo, hi);
+                    self.cache.insert((is_and, a, b), r);
+                    results.push(r);
+                }
+            }
+        }
+        results.pop().unwrap()
+    }
+
+    fn cofactors(&self, u: usize, v: usize) -> (usize, usize) {
+        match self.nodes[u] {
+            (var, lo, hi) if var == v => (lo, hi),
+            _ => (u, u),
+        }
+    }
+
+    /// Returns whether each node is reachable from `root`.
+    fn reachable(&self, root: usize) -> Vec<bool> {
+        let mut ans = vec![false; self.nodes.len()];
+        let mut stack = vec![root];
+        while let Some(u) = stack.pop() {
+            if !ans[u] {
+                ans[u] = true;
+                let (_, lo, hi) = self.nodes[u];
+                stack.extend([lo, hi]);
+            }
+        }
+        ans
+    }
+}
+
+/// Returns `count * 2^free`.
+fn scale(count: u128, free: usize) -> u128 {
+    if count == 0 {
+        return 0;
+    }
+    u32::try_from(free)
+        .ok()
+        .and_then(|n| 1_u128.checked_shl(n))
+        .and_then(|m| count.checked_mul(m))
+        .expect("model count overflows u128")
+}
+
+/// Counts the assignments of the variables of `expr` which satisfy it.
+///
+/// The size of the decision diagram, and so the time taken, is exponential in the worst case.
+///
+/// # Panics
+/// Panics if the count overflows `u128`, which is only possible with 128 or more variables.
+#[must_use]
+pub fn count_models<T>(expr: &Expr<T>) -> u128
+where
+    T: Eq + Hash,
+{
+    let (vars, indexed) = index_vars(expr);
+    let (bdd, root) = Bdd::new(&indexed, vars.len());
+    indexed.drop_iterative();
+
+    // The models of each node over the variables from its own level onwards.
+    let reachable = bdd.reachable(root);
+    let mut counts = vec![0; bdd.nodes.len()];
+    counts[TRUE] = 1;
+    for u in 2..bdd.nodes.len() {
+        if reachable[u] {
+            let (v, lo, hi) = bdd.nodes[u];
+            let lo = scale(counts[lo], bdd.level(lo) - v - 1);
+            let hi = scale(counts[hi], bdd.level(hi) - v - 1);
+            counts[u] = lo.checked_add(hi).expect("model count overflows u128");
+        }
+    }
+    scale(counts[root], bdd.level(root))
+}
+
+/// Returns the probability that `expr` is true
+/// when each variable is independently true with the probability given by `f`.
+#[must_use]
+pub fn probability<T, F>(expr: &Expr<T>, f: &F) -> f64
+where
+    T: Eq + Hash,
+    F: for<'v> Fn(&'v T) -> f64,
+{
+    let (vars, indexed) = index_vars(expr);
+    let (bdd, root) = Bdd::new(&indexed, vars.len());
+    indexed.drop_iterative();
+
+    let ps: Vec<f64> = vars.iter().map(|v| f(v)).collect();
+    let reachable = bdd.reachable(root);
+    let mut probs = vec![0.0; bdd.nodes.len()];
+    probs[TRUE] = 1.0;
+    for u in 2..bdd.nodes.len() {
+        if reachable[u] {
+            let (v, lo, hi) = bdd.nodes[u];
+            probs[u] = ps[v] * probs[hi] + (1.0 - ps[v]) * probs[lo];
+        }
+    }
+    probs[root]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::{All, Any, all, any, const_, expr, not, var};
+    use crate::cfg::ast::{flag, key_value};
+
+    #[test]
+    fn count() {
+        assert_eq!(count_models(&expr(any((var(0), var(1), var(2))))), 7);
+        assert_eq!(count_models(&expr(all((var(0), not(var(1)))))), 1);
+        assert_eq!(count_models(&expr(any((var(0), not(var(0)))))), 2);
+        assert_eq!(count_models(&expr(all((var(0), not(var(0)))))), 0);
+        assert_eq!(count_models(&expr(any((var(0), const_(true))))), 2);
+        assert_eq!(count_models::<u32>(&expr(const_(true))), 1);
+
+        let x = expr(any((all((var(0), var(1))), all((var(2), var(3))))));
+        assert_eq!(count_models(&x), 7);
+
+        let wide = expr(Any((0..127).map(|i| expr(var(i))).collect()));
+        assert_eq!(count_models(&wide), u128::MAX >> 1);
+
+        let many = expr(All((0..200).map(|i| expr(var(i))).collect()));
+        assert_eq!(count_models(&many), 1);
+    }
+
+    #[test]
+    fn count_pairs() {
+        let pairs = expr(All((0..60)
+            .map(|i| expr(any((var(2 * i), var(2 * i + 1)))))
+            .collect()));
+        assert_eq!(count_models(&pairs), 3_u128.pow(60));
+    }
+
+    #[test]
+    fn probability_of_features() {
+        let x = expr(all((
+            flag("unix"),
+            any((key_value("feature", "a"), key_value("feature", "b"))),
+        )));
+        let p = probability(&x, &|p| if *p == flag("unix") { 0.5 } else { 0.1 });
+        assert!((p - 0.5 * 0.19).abs() < 1e-12);
+
+        let x = expr(any((var(0), not(var(0)))));
+        assert!((probability(&x, &|_| 0.3) - 1.0).abs() < 1e-12);
+
+        let calls = std::cell::Cell::new(0);
+        let x = expr(any((var(0), all((var(1), var(2))), not(var(1)))));
+        let p = probability(&x, &|_| {
+            calls.set(calls.get() + 1);
+            0.5
+        });
+        assert!((p - 0.875).abs() < 1e-12);
+        assert_eq!(calls.get(), 3);
+    }
+}
@@ -0,0 +1,78 @@
+pub use crate::ast::{All, Any, Not, Var};
+
+use crate::infix::{Precedence, Tokens, fmt_infix};
+
+use std::fmt;
+
+pub type Expr = crate::ast::Expr<CPred>;
+
+/// A predicate of a C preprocessor `#if` condition.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum CPred {
+    /// `defined(X)`
+    Defined(String),
+    /// `X`, true when the macro expands to a non-zero value
+    Macro(String),
+}
+
+impl From<CPred> for Expr {
+    fn from(x: CPred) -> Self {
+        Expr::Var(Var(x))
+    }
+}
+
+pub fn defined(s: impl Into<String>) -> CPred {
+    CPred::Defined(s.into())
+}
+
+pub fn macro_(s: impl Into<String>) -> CPred {
+    CPred::Macro(s.into())
+}
+
+impl fmt::Display for CPred {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CPred::Defined(name) => write!(f, "defined({name})"),
+            CPred::Macro(name) => write!(f, "{name}"),
+        }
+    }
+}
+
+const TOKENS: Tokens = Tokens {
+    and: " && ",
+    or: " || ",
+    not: "!",
+    true_: "1",
+    false_: "0",
+};
+
+const PRECEDENCE: Precedence = Precedence { and: 2, or: 1 };
+
+/// Formats an expression as a preprocessor condition, e.g. `defined(A) && !(B || C)`.
+pub struct Condition<'a>(pub &'a Expr);
+
+impl fmt::Display for Condition<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt_infix(f, self.0, PRECEDENCE, &TOKENS, None, &|f, x| {
+            write!(f, "{x}")
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::{all, any, const_, expr, not};
+
+    #[test]
+    fn condition_string() {
+        let x = expr(all((defined("A"), not(any((macro_("B"), defined("C")))))));
+        assert_eq!(
+            Condition(&x).to_string(),
+            "defined(A) && !(B || defined(C))"
+        );
+
+        let x = expr(any((const_(false), all(()))));
+        assert_eq!(Condition(&x).to_string(), "0 || 1");
+    }
+}
@@ -0,0 +1,173 @@
+#![allow(clippy::missing_errors_doc)]
+
+use super::ast::{All, Any, CPred, Expr, Not, Var};
+
+use crate::parsing::{consume_tag, ensure, parse_identifier, skip_space0, skip_tag, take_while1};
+
+pub use crate::parsing::{Error, MAX_DEPTH, Result};
+
+/// Parses the condition of an `#if` or `#elif` directive.
+pub fn parse(mut s: &str) -> Result<'_, Expr> {
+    let s = &mut s;
+    skip_space0(s);
+    let expr = parse_or(s, 0)?;
+    skip_space0(s);
+    ensure!(s, s.is_empty());
+    Ok(expr)
+}
+
+/// Parses a whole `#if`, `#elif`, `#ifdef` or `#ifndef` directive.
+pub fn parse_directive(mut s: &str) -> Result<'_, Expr> {
+    let s = &mut s;
+    skip_space0(s);
+    consume_tag(s, "#")?;
+    skip_space0(s);
+    let directive = parse_identifier(s)?;
+    skip_space0(s);
+    ensure!(s, matches!(directive, "if" | "elif" | "ifdef" | "ifndef"));
+
+    if matches!(directive, "if" | "elif") {
+        return parse(s);
+    }
+
+    let name = parse_identifier(s)?;
+    skip_space0(s);
+    ensure!(s, s.is_empty());
+
+    let expr = Expr::Var(Var(CPred::Defined(name.into())));
+    if directive == "ifdef" {
+        Ok(expr)
+    } else {
+        Ok(Expr::Not(Not(Box::new(expr))))
+    }
+}
+
+fn parse_or<'p>(s: &mut &'p str, depth: usize) -> Result<'p, Expr> {
+    let mut list = vec![parse_and(s, depth)?];
+    skip_space0(s);
+    while skip_tag(s, "||").is_some() {
+        skip_space0(s);
+        list.push(parse_and(s, depth)?);
+        skip_space0(s);
+    }
+    Ok(if list.len() == 1 {
+        list.pop().unwrap()
+    } else {
+        Expr::Any(Any(list))
+    })
+}
+
+fn parse_and<'p>(s: &mut &'p str, depth: usize) -> Result<'p, Expr> {
+    let mut list = vec![parse_unary(s, depth)?];
+    skip_space0(s);
+    while skip_tag(s, "&&").is_some() {
+        skip_space0(s);
+        list.push(parse_unary(s, depth)?);
+        skip_space0(s);
+    }
+    Ok(if list.len() == 1 {
+        list.pop().unwrap()
+    } else {
+        Expr::All(All(list))
+    })
+}
+
+fn parse_unary<'p>(s: &mut &'p str, depth: usize) -> Result<'p, Expr> {
+    if s.starts_with('!') {
+        ensure!(s, depth < MAX_DEPTH);
+        consume_tag(s, "!")?;
+        skip_space0(s);
+        return Ok(Expr::Not(Not(Box::new(parse_unary(s, depth + 1)?))));
+    }
+
+    if s.starts_with('(') {
+        ensure!(s, depth < MAX_DEPTH);
+        consume_tag(s, "(")?;
+        skip_space0(s);
+        let expr = parse_or(s, depth + 1)?;
+        skip_space0(s);
+        consume_tag(s, ")")?;
+        return Ok(expr);
+    }
+
+    if s.starts_with(|c: char| c.is_ascii_digit()) {
+        let digits = take_while1(s, |c| c.is_ascii_digit())?;
+        skip_integer_suffix(s);
+        return Ok(Expr::Const(digits.bytes().any(|b| b != b'0')));
+    }
+
+    let name = parse_identifier(s)?;
+    if name != "defined" {
+        return Ok(Expr::Var(Var(CPred::Macro(name.into()))));
+    }
+
+    skip_space0(s);
+    let name = if skip_tag(s, "(").is_some() {
+        skip_space0(s);
+        let name = parse_identifier(s)?;
+        skip_space0(s);
+        consume_tag(s, ")")?;
+        name
+    } else {
+        parse_identifier(s)?
+    };
+    Ok(Expr::Var(Var(CPred::Defined(name.into()))))
+}
+
+fn skip_integer_suffix(s: &mut &str) {
+    *s = s.trim_start_matches(['u', 'U', 'l', 'L']);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::{all, any, const_, expr, not};
+    use crate::cpp::ast::{Condition, defined, macro_};
+    use crate::transforms::simplify;
+
+    #[test]
+    fn parse_condition() {
+        let x = parse("defined(A) && !defined B || (C && 0)").unwrap();
+        let expected = expr(any((
+            all((defined("A"), not(defined("B")))),
+            all((macro_("C"), const_(false))),
+        )));
+        assert_eq!(x, expected);
+        assert_eq!(
+            Condition(&x).to_string(),
+            "defined(A) && !defined(B) || C && 0"
+        );
+    }
+
+    #[test]
+    fn parse_directives() {
+        let x = parse_directive("#elif defined ( A ) || 1UL").unwrap();
+        assert_eq!(x, expr(any((defined("A"), const_(true)))));
+
+        let x = parse_directive("  #  ifndef GUARD_H").unwrap();
+        assert_eq!(x, expr(not(defined("GUARD_H"))));
+
+        assert!(parse_directive("#define A 1").is_err());
+        assert!(parse("defined()").is_err());
+        assert!(parse("A &&").is_err());
+    }
+
+    #[test]
+    fn simplify_generated() {
+        let mut x =
+            parse("!(defined(A) || defined(B)) && (defined(C) || 0) && !defined(A)").unwrap();
+        simplify(&mut x);
+        assert_eq!(
+            Condition(&x).to_string(),
+            "!defined(A) && !defined(B) && defined(C)"
+        );
+    }
+
+    #[test]
+    fn depth_limit() {
+        let nested = |n| format!("{}A{}", "!(".repeat(n / 2), ")".repeat(n / 2));
+        assert!(parse(&nested(MAX_DEPTH)).is_ok());
+        assert!(parse(&nested(MAX_DEPTH + 2)).is_err());
+        assert!(parse(&"(".repeat(1_000_000)).is_err());
+    }
+}
@@ -0,0 +1,328 @@
+#![allow(clippy::missing_errors_doc)]
+
+use crate::ast::{All, Any, Expr, Node, Not, Var, fold_ref};
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::hash::Hash;
+use std::io;
+
+/// A formula in conjunctive normal form with DIMACS literals.
+///
+/// Variables are numbered from 1. A negative literal is a negated variable.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Cnf {
+    pub num_vars: u32,
+    pub clauses: Vec<Vec<i32>>,
+}
+
+impl Cnf {
+    /// Encodes an expression with the Tseitin transformation.
+    ///
+    /// The result is equisatisfiable with `expr`. The variables of `expr` are numbered first,
+    /// in order of first occurrence, followed by auxiliary variables.
+    /// Returns the CNF and the variables of `expr`, where the variable `i` is at index `i - 1`.
+    ///
+    /// # Panics
+    /// Panics if the encoding needs more than `i32::MAX` variables.
+    pub fn tseitin<T>(expr: &Expr<T>) -> (Self, Vec<&T>)
+    where
+        T: Eq + Hash,
+    {
+        let vars = expr.vars();
+        let index: HashMap<&T, i32> = vars
+            .iter()
+            .enumerate()
+            .map(|(i, &v)| (v, i32::try_from(i + 1).unwrap()))
+            .collect();
+        let mut cnf = Cnf {
+            num_vars: u32::try_from(vars.len()).unwrap(),
+            clauses: Vec::new(),
+        };
+        let root = cnf.encode(expr, &index);
+        cnf.clauses.push(vec![root]);
+        (cnf, vars)
+    }
+
+    fn fresh(&mut self) -> i32 {
+        self.num_vars += 1;
+        i32::try_from(self.num_vars).unwrap()
+    }
+
+    fn encode<T: Eq + Hash>(&mut self, expr: &Expr<T>, index: &HashMap<&T, i32>) -> i32 {
+        fold_ref(expr, &mut |node: Node<&T, i32>| match node {
+            Node::Any(lits) => self.gate(&lits, -1),
+            Node::All(lits) => self.gate(&lits, 1),
+            Node::Not(l) => -l,
+            Node::Var(v) => index[v],
+            Node::Const(b) => {
+                let x = self.fresh();
+                self.clauses.push(vec![if b { x } else { -x }]);
+                x
+            }
+        })
+    }
+
+    fn gate(&mut self, lits: &[i32], sign: i32) -> i32 {
+        let x = self.fresh();
+
+        // all: x -> l, (l1 & ... & ln) -> x
+        // any: l -> x, x -> (l1 | ... | ln)
+        let mut long = vec![sign * x];
+        for &l in lits {
+            self.clauses.push(vec![-sign * x, sign * l]);
+            long.push(-sign * l);
+        }
+        self.clauses.push(long);
+        x
+    }
+
+    /// Converts the CNF into `all(any(..), ..)`.
+    ///
+    /// # Panics
+    /// Panics if a literal is zero.
+    #[must_use]
+    pub fn to_expr(&self) -> Expr<u32> {
+        let clauses = self.clauses.iter().map(|clause| {
+            let lits = clause.iter().map(|&l| {
+                assert_ne!(l, 0);
+                let var = Expr::Var(Var(l.unsigned_abs()));
+                if l > 0 {
+                    var
+                } else {
+                    Expr::Not(Not(Box::new(var)))
+                }
+            });
+            Expr::Any(Any(lits.collect()))
+        });
+        Expr::All(All(clauses.collect()))
+    }
+
+    pub fn write<W>(&self, out: &mut W) -> io::Result<()>
+    where
+        W: io::Write + ?Sized,
+    {
+        write!(out, "{self}")
+    }
+}
+
+impl fmt::Display for Cnf {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "p cnf {} {}", self.num_vars, self.clauses.len())?;
+        for clause in &self.clauses {
+            for l in clause {
+                write!(f, "{l} ")?;
+            }
+            writeln!(f, "0")?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    /// 1-based line number
+    pub line: usize,
+    pub kind: ParseErrorKind,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseErrorKind {
+    MissingHeader,
+    InvalidHeader,
+    InvalidLiteral(String),
+    VariableOutOfRange(i32),
+    UnterminatedClause,
+    ClauseCountMismatch { expected: usize, found: usize },
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: ", self.line)?;
+        match &self.kind {
+            ParseErrorKind::MissingHeader => write!(f, "missing `p cnf` header"),
+            ParseErrorKind::InvalidHeader => write!(f, "invalid `p cnf` header"),
+            ParseErrorKind::InvalidLiteral(s) => write!(f, "invalid literal {s:?}"),
+            ParseErrorKind::VariableOutOfRange(l) => {
+                write!(f, "variable of literal {l} out of range")
+            }
+            ParseErrorKind::UnterminatedClause => write!(f, "clause is not terminated by 0"),
+            ParseErrorKind::ClauseCountMismatch { expected, found } => {
+                write!(f, "expected {expected} clauses, found {found}")
+            }
+        }
+    }
+}
+
+impl Error for ParseError {}
+
+/// Parses a DIMACS `p cnf` file. Lines starting with `c` are comments.
+pub fn parse(s: &str) -> Result<Cnf, ParseError> {
+    let err = |line, kind| ParseError { line, kind };
+
+    let mut header: Option<(u32, usize)> = None;
+    let mut clauses = Vec::new();
+    let mut clause = Vec::new();
+    let mut last_line = 0;
+
+    for (i, line) in s.lines().enumerate() {
+        let line_no = i + 1;
+        last_line = line_no;
+        let line = line.trim();
+
+        if line.is_empty() || line.starts_with('c') {
+            continue;
+        }
+
+        // SATLIB benchmarks end with a `%` line
+        if line == "%" && header.is_some() {
+            break;
+        }
+
+        let Some((num_vars, _)) = header else {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let ["p", "cnf", v, c] = fields.as_slice() else {
+                let kind = if line.starts_with('p') {
+                    ParseErrorKind::InvalidHeader
+                } else {
+                    ParseErrorKind::MissingHeader
+                };
+                return Err(err(line_no, kind));
+            };
+            let v = v
+                .parse()
+                .map_err(|_| err(line_no, ParseErrorKind::InvalidHeader))?;
+            let c = c
+                .parse()
+                .map_err(|_| err(line_no, ParseErrorKind::InvalidHeader))?;
+            if i32::try_from(v).is_err() {
+                return Err(err(line_no, ParseErrorKind::InvalidHeader));
+            }
+            header = Some((v, c));
+            continue;
+        };
+
+        for token in line.split_whitespace() {
+            let l: i32 = token
+                .parse()
+                .map_err(|_| err(line_no, ParseErrorKind::InvalidLiteral(token.to_owned())))?;
+            if l == 0 {
+                clauses.push(std::mem::take(&mut clause));
+            } else if l.unsigned_abs() > num_vars {
+                return Err(err(line_no, ParseErrorKind::VariableOutOfRange(l)));
+            } else {
+                clause.push(l);
+            }
+        }
+    }
+
+    let Some((num_vars, num_clauses)) = header else {
+        return Err(err(last_line, ParseErrorKind::MissingHeader));
+    };
+    if !clause.is_empty() {
+        return Err(err(last_line, ParseErrorKind::UnterminatedClause));
+    }
+    if clauses.len() != num_clauses {
+        let kind = ParseErrorKind::ClauseCountMismatch {
+            expected: num_clauses,
+            found: clauses.len(),
+        };
+        return Err(err(last_line, kind));
+    }
+
+    Ok(Cnf { num_vars, clauses })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::{all, any, const_, expr, not, var};
+    use crate::eval::eval_with;
+
+    fn is_satisfied(cnf: &Cnf, assign: impl Fn(u32) -> bool) -> bool {
+        eval_with(&cnf.to_expr(), &|&x| assign(x))
+    }
+
+    #[test]
+    fn tseitin_equisatisfiable() {
+        let x = expr(any((
+            all((var('a'), not(var('b')))),
+            not(any((var('c'), const_(false)))),
+        )));
+        let (cnf, vars) = Cnf::tseitin(&x);
+        assert_eq!(vars, [&'a', &'b', &'c']);
+
+        let index: HashMap<&char, usize> = vars.iter().enumerate().map(|(i, &v)| (v, i)).collect();
+        let n = vars.len();
+        let aux = cnf.num_vars as usize - n;
+        for i in 0..1 << n {
+            let expected = eval_with(&x, &|v| (i >> index[v]) & 1 == 1);
+            let found = (0..1 << aux).any(|j| {
+                is_satisfied(&cnf, |v| {
+                    let v = v as usize - 1;
+                    if v < n {
+                        (i >> v) & 1 == 1
+                    } else {
+                        (j >> (v - n)) & 1 == 1
+                    }
+                })
+            });
+            assert_eq!(expected, found, "i = {i}");
+        }
+    }
+
+    #[test]
+    fn write_and_parse() {
+        let (cnf, _) = Cnf::tseitin(&expr(all((var(1), not(var(2))))));
+        let text = cnf.to_string();
+        assert_eq!(text, "p cnf 3 4\n-3 1 0\n-3 -2 0\n3 -1 2 0\n3 0\n");
+        assert_eq!(parse(&text).unwrap(), cnf);
+    }
+
+    #[test]
+    fn parse_with_comments() {
+        let text = "c example\nc\np cnf 3 2\n1 -3 0\n2 3\n-1 0\n%\n0\n";
+        let cnf = parse(text).unwrap();
+        assert_eq!(cnf.clauses, [vec![1, -3], vec![2, 3, -1]]);
+        assert_eq!(
+            cnf.to_expr().to_string(),
+            "all(any(1, not(3)), any(2, 3, not(1)))"
+        );
+    }
+
+    #[test]
+    fn parse_errors() {
+        let kind = |s| parse(s).unwrap_err().kind;
+        assert_eq!(kind("1 2 0\n"), ParseErrorKind::MissingHeader);
+        assert_eq!(kind("p cnf x 1\n"), ParseErrorKind::InvalidHeader);
+        assert_eq!(
+            kind("p cnf 2 1\n1 a 0\n"),
+            ParseErrorKind::InvalidLiteral("a".into())
+        );
+        assert_eq!(
+            kind("p cnf 2 1\n1 3 0\n"),
+            ParseErrorKind::VariableOutOfRange(3)
+        );
+        assert_eq!(kind("p cnf 2 1\n1 2\n"), ParseErrorKind::UnterminatedClause);
+        let mismatch = ParseErrorKind::ClauseCountMismatch {
+            expected: 2,
+            found: 1,
+        };
+        assert_eq!(kind("p cnf 2 2\n1 2 0\n"), mismatch);
+        assert_eq!(parse("p cnf 2 1\n1 a 0\n").unwrap_err().line, 2);
+    }
+
+    #[test]
+    fn deeply_nested() {
+        let mut x = expr(var(0));
+        for _ in 0..100_000 {
+            x = expr(all((not(x), const_(true))));
+        }
+        let (cnf, vars) = Cnf::tseitin(&x);
+        assert_eq!(vars, [&0]);
+        assert_eq!(cnf.num_vars, 1 + 2 * 100_000);
+        assert_eq!(cnf.clauses.len(), 4 * 100_000 + 1);
+        x.drop_iterative();
+    }
+}
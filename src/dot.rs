@@ -0,0 +1,312 @@
+use crate::ast::{All, Any, Expr, Not, Var};
+
+use std::collections::HashMap;
+use std::fmt::{self, Write as _};
+use std::slice;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DotOptions {
+    /// Renders structurally-identical subtrees once, turning the tree into a DAG.
+    ///
+    /// Variables are identified by their label, so variables which print the same are shared.
+    pub share: bool,
+}
+
+/// Renders an expression as a Graphviz `digraph`.
+#[must_use]
+pub fn to_dot<T>(expr: &Expr<T>, options: DotOptions) -> String
+where
+    T: fmt::Display,
+{
+    let mut out = String::new();
+    out.push_str("digraph expr {\n");
+    write_header(&mut out);
+    Writer::new(&mut out, "n", options).write(expr);
+    out.push_str("}\n");
+    out
+}
+
+/// Renders two expressions side by side, e.g. before and after a transform.
+#[must_use]
+pub fn to_dot_diff<T>(before: &Expr<T>, after: &Expr<T>, options: DotOptions) -> String
+where
+    T: fmt::Display,
+{
+    let mut out = String::new();
+    out.push_str("digraph diff {\n");
+    write_header(&mut out);
+    for (name, prefix, expr) in [("before", "b", before), ("after", "a", after)] {
+        writeln!(out, "  subgraph cluster_{name} {{").unwrap();
+        writeln!(out, "  label=\"{name}\";").unwrap();
+        Writer::new(&mut out, prefix, options).write(expr);
+        out.push_str("  }\n");
+    }
+    out.push_str("}\n");
+    out
+}
+
+fn write_header(out: &mut String) {
+    out.push_str("  ordering=out;\n");
+    out.push_str("  node [fontname=\"monospace\"];\n");
+}
+
+fn escape(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+#[derive(PartialEq, Eq, Hash)]
+enum Key {
+    Any(Vec<usize>),
+    All(Vec<usize>),
+    Not(usize),
+    Var(String),
+    Const(bool),
+}
+
+fn children<T>(expr: &Expr<T>) -> &[Expr<T>] {
+    match expr {
+        Expr::Any(Any(list)) | Expr::All(All(list)) => list,
+        Expr::Not(Not(not)) => slice::from_ref(&**not),
+        Expr::Var(_) | Expr::Const(_) => &[],
+    }
+}
+
+/// Numbers the structurally-distinct subtrees by hash-consing, bottom-up.
+///
+/// Returns the class and subtree size of every node in pre-order.
+fn classify<T>(expr: &Expr<T>) -> Vec<(usize, usize)>
+where
+    T: fmt::Display,
+{
+    let mut nodes = Vec::new();
+    let mut parents = Vec::new();
+    let mut stack = vec![(expr, None)];
+    while let Some((expr, parent)) = stack.pop() {
+        let slot = nodes.len();
+        nodes.push(expr);
+        parents.push(parent);
+        stack.extend(children(expr).iter().rev().map(|x| (x, Some(slot))));
+    }
+
+    // the subtree of a node follows it in pre-order, so it is complete when the node is reached
+    let mut table = HashMap::new();
+    let mut ans = vec![(0, 1); nodes.len()];
+    for (slot, &expr) in nodes.iter().enumerate().rev() {
+        let mut child = slot + 1;
+        let mut list = Vec::new();
+        for _ in children(expr) {
+            list.push(ans[child].0);
+            child += ans[child].1;
+        }
+        let key = match expr {
+            Expr::Any(_) => Key::Any(list),
+            Expr::All(_) => Key::All(list),
+            Expr::Not(_) => Key::Not(list[0]),
+            Expr::Var(Var(x)) => Key::Var(x.to_string()),
+            Expr::Const(b) => Key::Const(*b),
+        };
+        let next = table.len();
+        ans[slot].0 = *table.entry(key).or_insert(next);
+        if let Some(parent) = parents[slot] {
+            ans[parent].1 += ans[slot].1;
+        }
+    }
+    ans
+}
+
+struct Writer<'o> {
+    out: &'o mut String,
+    prefix: &'static str,
+    options: DotOptions,
+    /// The class and subtree size of every node in pre-order, in share mode.
+    classes: Vec<(usize, usize)>,
+    pos: usize,
+    seen: HashMap<usize, usize>,
+    next: usize,
+}
+
+impl<'o> Writer<'o> {
+    fn new(out: &'o mut String, prefix: &'static str, options: DotOptions) -> Self {
+        Self {
+            out,
+            prefix,
+            options,
+            classes: Vec::new(),
+            pos: 0,
+            seen: HashMap::new(),
+            next: 0,
+        }
+    }
+
+    fn write<T: fmt::Display>(&mut self, expr: &Expr<T>) {
+        if self.options.share {
+            self.classes = classify(expr);
+        }
+        self.node(expr);
+    }
+
+    fn node<T: fmt::Display>(&mut self, expr: &Expr<T>) {
+        enum Item<'e, T> {
+            Expr(&'e Expr<T>, Option<usize>),
+            Edge(usize, usize),
+        }
+
+        let prefix = self.prefix;
+        let mut stack = vec![Item::Expr(expr, None)];
+        while let Some(item) = stack.pop() {
+            let (expr, parent) = match item {
+                Item::Expr(expr, parent) => (expr, parent),
+                Item::Edge(from, to) => {
+                    writeln!(self.out, "  {prefix}{from} -> {prefix}{to};").unwrap();
+                    continue;
+                }
+            };
+
+            let id = self.next;
+            if self.options.share {
+                let (class, size) = self.classes[self.pos];
+                if let Some(&id) = self.seen.get(&class) {
+                    self.pos += size;
+                    if let Some(parent) = parent {
+                        stack.push(Item::Edge(parent, id));
+                    }
+                    continue;
+                }
+                self.pos += 1;
+                self.seen.insert(class, id);
+            }
+            self.next += 1;
+
+            let (label, shape) = match expr {
+                Expr::Any(_) => ("any".to_owned(), "box"),
+                Expr::All(_) => ("all".to_owned(), "box"),
+                Expr::Not(_) => ("not".to_owned(), "box"),
+                Expr::Var(Var(x)) => (escape(&x.to_string()), "ellipse"),
+                Expr::Const(b) => (b.to_string(), "plaintext"),
+            };
+            writeln!(
+                self.out,
+                "  {prefix}{id} [label=\"{label}\", shape={shape}];"
+            )
+            .unwrap();
+
+            // the edge from the parent follows the subtree
+            if let Some(parent) = parent {
+                stack.push(Item::Edge(parent, id));
+            }
+            stack.extend(children(expr).iter().rev().map(|x| Item::Expr(x, Some(id))));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::{all, any, const_, expr, not, var};
+    use crate::transforms::flatten_by_de_morgan::FlattenByDeMorgan;
+    use crate::visit_mut::VisitMut;
+
+    #[test]
+    fn tree() {
+        let x = expr(any((var("a"), not(var("a")), const_(true))));
+        let expected = r#"digraph expr {
+  ordering=out;
+  node [fontname="monospace"];
+  n0 [label="any", shape=box];
+  n1 [label="a", shape=ellipse];
+  n0 -> n1;
+  n2 [label="not", shape=box];
+  n3 [label="a", shape=ellipse];
+  n2 -> n3;
+  n0 -> n2;
+  n4 [label="true", shape=plaintext];
+  n0 -> n4;
+}
+"#;
+        assert_eq!(to_dot(&x, DotOptions::default()), expected);
+    }
+
+    #[test]
+    fn shared() {
+        let x = expr(all((var("a"), not(var("a")), not(var("a")))));
+        let expected = r#"digraph expr {
+  ordering=out;
+  node [fontname="monospace"];
+  n0 [label="all", shape=box];
+  n1 [label="a", shape=ellipse];
+  n0 -> n1;
+  n2 [label="not", shape=box];
+  n2 -> n1;
+  n0 -> n2;
+  n0 -> n2;
+}
+"#;
+        assert_eq!(to_dot(&x, DotOptions { share: true }), expected);
+
+        let x = expr(any((
+            all((var("a"), var("b"))),
+            not(all((var("a"), var("b")))),
+            all((var("b"), var("a"))),
+        )));
+        let dot = to_dot(&x, DotOptions { share: true });
+        assert_eq!(dot.matches("label=\"all\"").count(), 2);
+        assert_eq!(dot.matches("label=\"a\"").count(), 1);
+        assert!(dot.contains("  n4 -> n1;\n  n0 -> n4;\n"));
+        assert!(dot.contains("  n5 -> n3;\n  n5 -> n2;\n"));
+    }
+
+    #[test]
+    fn display_only() {
+        struct Label(&'static str);
+
+        impl fmt::Display for Label {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.write_str(self.0)
+            }
+        }
+
+        let x = expr(any((var(Label("a")), not(var(Label("a"))))));
+        assert_eq!(
+            to_dot(&x, DotOptions::default())
+                .matches("label=\"a\"")
+                .count(),
+            2
+        );
+        let dot = to_dot(&x, DotOptions { share: true });
+        assert_eq!(dot.matches("label=\"a\"").count(), 1);
+        assert!(dot.contains("  n2 -> n1;\n"));
+    }
+
+    #[test]
+    fn diff() {
+        let before = expr(not(any((var("target_os = \"linux\""), var("b")))));
+        let mut after = before.clone();
+        FlattenByDeMorgan.visit_mut_expr(&mut after);
+
+        let dot = to_dot_diff(&before, &after, DotOptions::default());
+        assert!(dot.starts_with("digraph diff {\n"));
+        assert!(dot.contains(
+            "  subgraph cluster_before {\n  label=\"before\";\n  b0 [label=\"not\", shape=box];\n"
+        ));
+        assert!(dot.contains(
+            "  subgraph cluster_after {\n  label=\"after\";\n  a0 [label=\"all\", shape=box];\n"
+        ));
+        assert!(dot.contains(r#"b2 [label="target_os = \"linux\"", shape=ellipse];"#));
+    }
+
+    #[test]
+    fn deeply_nested() {
+        let mut x = expr(var("a"));
+        for _ in 0..100_000 {
+            x = expr(all((not(x), const_(true))));
+        }
+        let dot = to_dot(&x, DotOptions::default());
+        assert_eq!(dot.matches(" -> ").count(), 3 * 100_000);
+        assert!(dot.ends_with("  n0 -> n300000;\n}\n"));
+
+        let dot = to_dot(&x, DotOptions { share: true });
+        assert_eq!(dot.matches("label=\"true\"").count(), 1);
+        assert_eq!(dot.matches(" -> ").count(), 3 * 100_000);
+        x.drop_iterative();
+    }
+}
@@ -1,18 +1,248 @@
-use crate::ast::{All, Any, Expr, Not, Var};
+#![allow(clippy::missing_errors_doc)]
+
+use crate::ast::{All, Any, Expr, Node, Not, Var, fold_ref};
+use crate::transforms::eval_const::EvalConst;
+use crate::visit_mut::VisitMut;
+
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::convert::Infallible;
+use std::hash::{BuildHasher, Hash};
+use std::slice;
 
 pub fn eval_with<T, F>(expr: &Expr<T>, f: &F) -> bool
 where
     F: for<'v> Fn(&'v T) -> bool,
 {
-    match expr {
-        Expr::Any(Any(list)) => list.iter().any(|e| eval_with(e, f)),
-        Expr::All(All(list)) => list.iter().all(|e| eval_with(e, f)),
-        Expr::Not(Not(not)) => !eval_with(not, f),
-        Expr::Var(Var(var)) => f(var),
-        Expr::Const(b) => *b,
+    match try_eval_with(expr, &mut |x| Ok::<_, Infallible>(f(x))) {
+        Ok(b) => b,
+        Err(e) => match e {},
+    }
+}
+
+/// An environment giving the value of every variable.
+pub trait Env<T> {
+    fn lookup(&self, var: &T) -> bool;
+}
+
+impl<T, F> Env<T> for F
+where
+    F: Fn(&T) -> bool,
+{
+    fn lookup(&self, var: &T) -> bool {
+        self(var)
+    }
+}
+
+/// The variables in the set are true.
+impl<T, S> Env<T> for HashSet<T, S>
+where
+    T: Eq + Hash,
+    S: BuildHasher,
+{
+    fn lookup(&self, var: &T) -> bool {
+        self.contains(var)
+    }
+}
+
+/// The variables in the set are true.
+impl<T> Env<T> for BTreeSet<T>
+where
+    T: Ord,
+{
+    fn lookup(&self, var: &T) -> bool {
+        self.contains(var)
+    }
+}
+
+/// Missing variables are false.
+impl<T, S> Env<T> for HashMap<T, bool, S>
+where
+    T: Eq + Hash,
+    S: BuildHasher,
+{
+    fn lookup(&self, var: &T) -> bool {
+        self.get(var).copied().unwrap_or(false)
+    }
+}
+
+/// The variables in the slice are true.
+impl<T> Env<T> for [T]
+where
+    T: PartialEq,
+{
+    fn lookup(&self, var: &T) -> bool {
+        self.contains(var)
+    }
+}
+
+impl<T> Expr<T> {
+    pub fn eval<E>(&self, env: &E) -> bool
+    where
+        E: Env<T> + ?Sized,
+    {
+        eval_with(self, &|x| env.lookup(x))
+    }
+}
+
+/// Evaluates with a fallible lookup, stopping at the first error.
+///
+/// Variables are looked up in short-circuit order, so a variable which does not affect the result is not looked up.
+/// The expression is walked with an explicit stack, so deep nesting cannot overflow the call stack.
+pub fn try_eval_with<T, E, F>(expr: &Expr<T>, f: &mut F) -> Result<bool, E>
+where
+    F: for<'v> FnMut(&'v T) -> Result<bool, E>,
+{
+    enum Frame<'a, T> {
+        List {
+            any: bool,
+            rest: slice::Iter<'a, Expr<T>>,
+        },
+        Not,
+    }
+
+    let mut stack: Vec<Frame<'_, T>> = Vec::new();
+    let mut cur = expr;
+    loop {
+        let mut val = loop {
+            match cur {
+                Expr::Any(Any(list)) | Expr::All(All(list)) => {
+                    let any = cur.is_any();
+                    let mut rest = list.iter();
+                    let Some(first) = rest.next() else {
+                        break !any;
+                    };
+                    stack.push(Frame::List { any, rest });
+                    cur = first;
+                }
+                Expr::Not(Not(not)) => {
+                    stack.push(Frame::Not);
+                    cur = not;
+                }
+                Expr::Var(Var(var)) => break f(var)?,
+                Expr::Const(b) => break *b,
+            }
+        };
+        loop {
+            match stack.last_mut() {
+                None => return Ok(val),
+                Some(Frame::Not) => val = !val,
+                Some(Frame::List { any, rest }) => {
+                    if val != *any {
+                        if let Some(next) = rest.next() {
+                            cur = next;
+                            break;
+                        }
+                    }
+                }
+            }
+            stack.pop();
+        }
+    }
+}
+
+/// Evaluates with a stateful lookup.
+pub fn eval_with_mut<T, F>(expr: &Expr<T>, f: &mut F) -> bool
+where
+    F: for<'v> FnMut(&'v T) -> bool,
+{
+    match try_eval_with(expr, &mut |x| Ok::<_, Infallible>(f(x))) {
+        Ok(b) => b,
+        Err(e) => match e {},
+    }
+}
+
+/// Caches the results of an expensive lookup, e.g. probing the filesystem.
+///
+/// Errors are not cached.
+#[derive(Debug, Clone)]
+pub struct Memo<T, F> {
+    f: F,
+    cache: HashMap<T, bool>,
+}
+
+impl<T, F> Memo<T, F>
+where
+    T: Clone + Eq + Hash,
+{
+    pub fn new(f: F) -> Self {
+        Self {
+            f,
+            cache: HashMap::new(),
+        }
+    }
+
+    #[must_use]
+    pub fn cache(&self) -> &HashMap<T, bool> {
+        &self.cache
+    }
+
+    pub fn get(&mut self, x: &T) -> bool
+    where
+        F: FnMut(&T) -> bool,
+    {
+        if let Some(&b) = self.cache.get(x) {
+            return b;
+        }
+        let b = (self.f)(x);
+        self.cache.insert(x.clone(), b);
+        b
+    }
+
+    pub fn try_get<E>(&mut self, x: &T) -> Result<bool, E>
+    where
+        F: FnMut(&T) -> Result<bool, E>,
+    {
+        if let Some(&b) = self.cache.get(x) {
+            return Ok(b);
+        }
+        let b = (self.f)(x)?;
+        self.cache.insert(x.clone(), b);
+        Ok(b)
     }
 }
 
+/// Substitutes the known variables and folds the constants.
+///
+/// `f` returns `None` for an unknown variable, which is kept in the result.
+pub fn partial_eval<T, F>(expr: &Expr<T>, f: &F) -> Expr<T>
+where
+    T: Clone,
+    F: for<'v> Fn(&'v T) -> Option<bool>,
+{
+    let mut ans = fold_ref(expr, &mut |node| match node {
+        Node::Var(var) => match f(var) {
+            Some(b) => Expr::Const(b),
+            None => Expr::Var(Var(var.clone())),
+        },
+        node => node.into_expr(T::clone),
+    });
+    EvalConst.visit_mut_expr(&mut ans);
+    ans
+}
+
+/// Evaluates under Kleene's three-valued logic, where `None` is unknown.
+pub fn eval3<T, F>(expr: &Expr<T>, f: &F) -> Option<bool>
+where
+    F: for<'v> Fn(&'v T) -> Option<bool>,
+{
+    let list_value = |list: &[Option<bool>], decisive: bool| {
+        if list.contains(&Some(decisive)) {
+            Some(decisive)
+        } else if list.contains(&None) {
+            None
+        } else {
+            Some(!decisive)
+        }
+    };
+    fold_ref(expr, &mut |node| match node {
+        Node::Any(list) => list_value(&list, true),
+        Node::All(list) => list_value(&list, false),
+        Node::Not(not) => not.map(|b| !b),
+        Node::Var(var) => f(var),
+        Node::Const(b) => Some(b),
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -32,4 +262,99 @@ mod tests {
             assert_eq!(lhs, rhs, "i = {i}");
         }
     }
+
+    #[test]
+    fn partial() {
+        let x = expr(all((any((var(0), var(1))), not(var(2)), var(3))));
+        let known = |x: &u32| matches!(x, 0 | 2).then_some(false);
+        assert_eq!(partial_eval(&x, &known).to_string(), "all(any(1), 3)");
+
+        let known = |x: &u32| (*x == 2).then_some(true);
+        assert_eq!(partial_eval(&x, &known).to_string(), "false");
+    }
+
+    #[test]
+    fn kleene() {
+        let x = expr(any((var(0), not(var(1)))));
+        let cases = [
+            (None, None, None),
+            (Some(true), None, Some(true)),
+            (None, Some(false), Some(true)),
+            (Some(false), Some(true), Some(false)),
+            (Some(false), None, None),
+        ];
+        for (a, b, expected) in cases {
+            let f = |x: &u32| if *x == 0 { a } else { b };
+            assert_eq!(eval3(&x, &f), expected, "{a:?} {b:?}");
+        }
+
+        assert_eq!(eval3(&expr(all(())), &|_: &u32| None), Some(true));
+        assert_eq!(eval3(&expr(any(())), &|_: &u32| None), Some(false));
+    }
+
+    #[test]
+    fn fallible() {
+        let x = expr(any((all((var(0), var(1))), var(2))));
+
+        let mut seen = Vec::new();
+        let ans = try_eval_with(&x, &mut |&v: &u32| {
+            seen.push(v);
+            if v == 2 { Err(v) } else { Ok(v == 1) }
+        });
+        assert_eq!(ans, Err(2));
+        assert_eq!(seen, [0, 2]);
+
+        let ans = try_eval_with(&x, &mut |&v: &u32| Ok::<_, ()>(v == 0));
+        assert_eq!(ans, Ok(false));
+    }
+
+    #[test]
+    fn memoized() {
+        let x = expr(all((
+            any((var(0), var(1))),
+            any((var(1), var(0))),
+            not(var(0)),
+        )));
+
+        let mut calls = 0;
+        let mut memo = Memo::new(|&v: &u32| {
+            calls += 1;
+            v == 1
+        });
+        assert!(eval_with_mut(&x, &mut |v| memo.get(v)));
+        assert_eq!(memo.cache().len(), 2);
+        drop(memo);
+        assert_eq!(calls, 2);
+
+        let mut memo = Memo::new(|&v: &u32| if v == 0 { Err("io error") } else { Ok(true) });
+        assert_eq!(try_eval_with(&x, &mut |v| memo.try_get(v)), Err("io error"));
+        assert!(memo.cache().is_empty());
+    }
+
+    #[test]
+    fn envs() {
+        let x = expr(all((var("a"), not(var("b")))));
+
+        assert!(x.eval(&HashSet::from(["a"])));
+        assert!(!x.eval(&BTreeSet::from(["a", "b"])));
+        assert!(x.eval(&HashMap::from([("a", true), ("b", false)])));
+        assert!(!x.eval(&HashMap::from([("b", false)])));
+        assert!(x.eval(["a", "c"].as_slice()));
+        assert!(!x.eval(&|v: &&str| v.len() == 1));
+    }
+
+    #[test]
+    fn deeply_nested() {
+        let mut x = expr(var(0));
+        for _ in 0..100_000 {
+            x = expr(all((not(x), const_(true))));
+        }
+        assert!(eval_with(&x, &|_| true));
+        let y = expr(not(x.clone()));
+        assert!(!eval_with(&y, &|_| true));
+
+        assert_eq!(partial_eval(&x, &|_| Some(false)).to_string(), "false");
+        x.drop_iterative();
+        y.drop_iterative();
+    }
 }
@@ -0,0 +1,215 @@
+//! Explains why an expression evaluates to its result.
+
+use crate::ast::{All, Any, Expr, Not, Var};
+
+use std::fmt;
+use std::slice;
+
+/// The evaluation of an expression, with the sub-expressions which determine it.
+///
+/// A true `any` and a false `all` are explained by the first child deciding the result,
+/// as visited by [`eval_with`](crate::eval::eval_with).
+/// A false `any` and a true `all` are explained by all of their children.
+///
+/// Building, printing and [`witness`](Explanation::witness) do not recurse.
+/// Cloning, `Debug` and dropping do, so deep explanations should be dropped with
+/// [`Explanation::drop_iterative`].
+#[derive(Debug, Clone)]
+pub struct Explanation<'a, T> {
+    pub expr: &'a Expr<T>,
+    pub value: bool,
+    pub because: Vec<Explanation<'a, T>>,
+}
+
+/// A node whose children are being explained.
+///
+/// `decisive` is `None` for a `not`, which is explained by its only child.
+struct Frame<'a, T> {
+    expr: &'a Expr<T>,
+    list: &'a [Expr<T>],
+    decisive: Option<bool>,
+    because: Vec<Explanation<'a, T>>,
+}
+
+pub fn explain<'a, T, F>(expr: &'a Expr<T>, f: &F) -> Explanation<'a, T>
+where
+    F: for<'v> Fn(&'v T) -> bool,
+{
+    let leaf = |expr, value| Explanation {
+        expr,
+        value,
+        because: Vec::new(),
+    };
+
+    let mut stack: Vec<Frame<'a, T>> = Vec::new();
+    let mut cur = expr;
+    'descend: loop {
+        let mut ans = loop {
+            match cur {
+                Expr::Any(Any(list)) | Expr::All(All(list)) => {
+                    let decisive = matches!(cur, Expr::Any(_));
+                    let Some(first) = list.first() else {
+                        break leaf(cur, !decisive);
+                    };
+                    stack.push(Frame {
+                        expr: cur,
+                        list,
+                        decisive: Some(decisive),
+                        because: Vec::with_capacity(list.len()),
+                    });
+                    cur = first;
+                }
+                Expr::Not(Not(not)) => {
+                    stack.push(Frame {
+                        expr: cur,
+                        list: slice::from_ref(not),
+                        decisive: None,
+                        because: Vec::with_capacity(1),
+                    });
+                    cur = not;
+                }
+                Expr::Var(Var(x)) => break leaf(cur, f(x)),
+                Expr::Const(b) => break leaf(cur, *b),
+            }
+        };
+
+        loop {
+            let Some(frame) = stack.last_mut() else {
+                return ans;
+            };
+            match frame.decisive {
+                Some(decisive) if ans.value != decisive => {
+                    frame.because.push(ans);
+                    if let Some(next) = frame.list.get(frame.because.len()) {
+                        cur = next;
+                        continue 'descend;
+                    }
+                    let Some(frame) = stack.pop() else {
+                        unreachable!()
+                    };
+                    ans = Explanation {
+                        expr: frame.expr,
+                        value: !decisive,
+                        because: frame.because,
+                    };
+                }
+                decisive => {
+                    let expr = frame.expr;
+                    stack.pop();
+                    ans = Explanation {
+                        expr,
+                        value: decisive.unwrap_or(!ans.value),
+                        because: vec![ans],
+                    };
+                }
+            }
+        }
+    }
+}
+
+impl<'a, T> Explanation<'a, T> {
+    /// Returns the valuations of the variables which determine the result, in first-visit order.
+    #[must_use]
+    pub fn witness(&self) -> Vec<(&'a T, bool)>
+    where
+        T: Eq,
+    {
+        let mut ans: Vec<(&'a T, bool)> = Vec::new();
+        let mut stack = vec![self];
+        while let Some(e) = stack.pop() {
+            if let Expr::Var(Var(x)) = e.expr {
+                if !ans.iter().any(|(y, _)| *y == x) {
+                    ans.push((x, e.value));
+                }
+            }
+            stack.extend(e.because.iter().rev());
+        }
+        ans
+    }
+
+    /// Drops the explanation with an explicit stack.
+    ///
+    /// The implicit drop recurses, so use this for explanations nested too deeply for the call stack.
+    pub fn drop_iterative(self) {
+        let mut stack = vec![self];
+        while let Some(mut e) = stack.pop() {
+            stack.append(&mut e.because);
+        }
+    }
+}
+
+impl<T> fmt::Display for Explanation<'_, T>
+where
+    T: fmt::Display,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut stack = vec![(self, 0)];
+        let mut first = true;
+        while let Some((e, depth)) = stack.pop() {
+            if !first {
+                writeln!(f)?;
+            }
+            first = false;
+
+            let indent = depth * 4;
+            write!(f, "{:indent$}`{}` is {}", "", e.expr, e.value)?;
+            if !e.because.is_empty() {
+                f.write_str(" because")?;
+            }
+            stack.extend(e.because.iter().rev().map(|c| (c, depth + 1)));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cfg::ast::{all, any, expr, flag, not, target_os};
+
+    #[test]
+    fn short_circuit() {
+        let x = expr(all((
+            flag("unix"),
+            any((target_os("linux"), target_os("android"))),
+            not(flag("miri")),
+        )));
+        let cfg = [flag("unix"), target_os("macos")];
+        let e = explain(&x, &|p| cfg.contains(p));
+
+        assert!(!e.value);
+        let expected = "\
+`all(unix, any(target_os = \"linux\", target_os = \"android\"), not(miri))` is false because
+    `any(target_os = \"linux\", target_os = \"android\")` is false because
+        `target_os = \"linux\"` is false
+        `target_os = \"android\"` is false";
+        assert_eq!(e.to_string(), expected);
+        assert_eq!(
+            e.witness(),
+            [(&target_os("linux"), false), (&target_os("android"), false)]
+        );
+    }
+
+    #[test]
+    fn true_result() {
+        let x = expr(any((not(flag("windows")), flag("unix"), flag("windows"))));
+        let e = explain(&x, &|p| *p == flag("unix"));
+
+        assert!(e.value);
+        assert_eq!(e.witness(), [(&flag("windows"), false)]);
+    }
+
+    #[test]
+    fn deeply_nested() {
+        let mut x = expr(flag("unix"));
+        for _ in 0..100_000 {
+            x = expr(all((not(x), flag("unix"))));
+        }
+        let e = explain(&x, &|p| *p == flag("unix"));
+
+        assert!(e.value);
+        assert_eq!(e.witness(), [(&flag("unix"), true)]);
+        e.drop_iterative();
+        x.drop_iterative();
+    }
+}
@@ -0,0 +1,404 @@
+#![allow(clippy::missing_errors_doc)]
+
+use crate::ast::{All, Any, Expr, Not, Var};
+use crate::parsing::{consume_tag, ensure, skip_space0, take_while1};
+
+use std::fmt;
+
+pub use crate::parsing::{Error, MAX_DEPTH, Result};
+
+/// Binding strength of the binary operators. Higher binds tighter.
+///
+/// Operators with equal precedence associate to the left.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Precedence {
+    pub and: u8,
+    pub or: u8,
+}
+
+impl Default for Precedence {
+    fn default() -> Self {
+        Self { and: 2, or: 1 }
+    }
+}
+
+/// Operator spelling used by the printer. The parser accepts all spellings.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Style {
+    /// `&&`, `||`, `!`
+    #[default]
+    Symbol,
+    /// `and`, `or`, `not`
+    Keyword,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Syntax {
+    pub precedence: Precedence,
+    pub style: Style,
+}
+
+pub(crate) struct Tokens {
+    pub and: &'static str,
+    pub or: &'static str,
+    pub not: &'static str,
+    pub true_: &'static str,
+    pub false_: &'static str,
+}
+
+impl Style {
+    fn tokens(self) -> &'static Tokens {
+        match self {
+            Style::Symbol => &Tokens {
+                and: " && ",
+                or: " || ",
+                not: "!",
+                true_: "true",
+                false_: "false",
+            },
+            Style::Keyword => &Tokens {
+                and: " and ",
+                or: " or ",
+                not: "not ",
+                true_: "true",
+                false_: "false",
+            },
+        }
+    }
+}
+
+const KEYWORDS: &[&str] = &["and", "or", "not", "true", "false"];
+
+pub fn parse(s: &str) -> Result<'_, Expr<String>> {
+    parse_with(s, Syntax::default())
+}
+
+pub fn parse_with(mut s: &str, syntax: Syntax) -> Result<'_, Expr<String>> {
+    let s = &mut s;
+    skip_space0(s);
+    let expr = parse_expr(s, syntax, 0, 0)?;
+    skip_space0(s);
+    ensure!(s, s.is_empty());
+    Ok(expr)
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum BinOp {
+    And,
+    Or,
+}
+
+fn is_ident_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_'
+}
+
+fn starts_with_keyword(s: &str, kw: &str) -> bool {
+    s.starts_with(kw) && !s[kw.len()..].starts_with(is_ident_char)
+}
+
+fn peek_bin_op(s: &str) -> Option<(BinOp, usize)> {
+    if s.starts_with("&&") {
+        Some((BinOp::And, 2))
+    } else if s.starts_with("||") {
+        Some((BinOp::Or, 2))
+    } else if s.starts_with('&') {
+        Some((BinOp::And, 1))
+    } else if s.starts_with('|') {
+        Some((BinOp::Or, 1))
+    } else if starts_with_keyword(s, "and") {
+        Some((BinOp::And, 3))
+    } else if starts_with_keyword(s, "or") {
+        Some((BinOp::Or, 2))
+    } else {
+        None
+    }
+}
+
+fn parse_expr<'p>(
+    s: &mut &'p str,
+    syntax: Syntax,
+    min_prec: u16,
+    depth: usize,
+) -> Result<'p, Expr<String>> {
+    let mut lhs = parse_unary(s, syntax, depth)?;
+
+    loop {
+        skip_space0(s);
+        let Some((op, len)) = peek_bin_op(s) else {
+            break;
+        };
+        let prec = u16::from(match op {
+            BinOp::And => syntax.precedence.and,
+            BinOp::Or => syntax.precedence.or,
+        });
+        if prec < min_prec {
+            break;
+        }
+        *s = &s[len..];
+        skip_space0(s);
+
+        let rhs = parse_expr(s, syntax, prec + 1, depth)?;
+
+        lhs = match (op, lhs) {
+            (BinOp::And, Expr::All(mut all)) => {
+                all.0.push(rhs);
+                Expr::All(all)
+            }
+            (BinOp::Or, Expr::Any(mut any)) => {
+                any.0.push(rhs);
+                Expr::Any(any)
+            }
+            (BinOp::And, lhs) => Expr::All(All(vec![lhs, rhs])),
+            (BinOp::Or, lhs) => Expr::Any(Any(vec![lhs, rhs])),
+        };
+    }
+
+    Ok(lhs)
+}
+
+fn parse_unary<'p>(s: &mut &'p str, syntax: Syntax, depth: usize) -> Result<'p, Expr<String>> {
+    if s.starts_with('!') || s.starts_with('~') {
+        ensure!(s, depth < MAX_DEPTH);
+        *s = &s[1..];
+        skip_space0(s);
+        return Ok(Expr::Not(Not(Box::new(parse_unary(s, syntax, depth + 1)?))));
+    }
+    if starts_with_keyword(s, "not") {
+        ensure!(s, depth < MAX_DEPTH);
+        *s = &s[3..];
+        skip_space0(s);
+        return Ok(Expr::Not(Not(Box::new(parse_unary(s, syntax, depth + 1)?))));
+    }
+    if s.starts_with('(') {
+        ensure!(s, depth < MAX_DEPTH);
+        consume_tag(s, "(")?;
+        skip_space0(s);
+        let expr = parse_expr(s, syntax, 0, depth + 1)?;
+        skip_space0(s);
+        consume_tag(s, ")")?;
+        return Ok(expr);
+    }
+
+    ensure!(
+        s,
+        s.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+    );
+    let ident = take_while1(s, is_ident_char)?;
+    match ident {
+        "true" => Ok(Expr::Const(true)),
+        "false" => Ok(Expr::Const(false)),
+        _ => {
+            ensure!(s, !KEYWORDS.contains(&ident));
+            Ok(Expr::Var(Var(ident.to_owned())))
+        }
+    }
+}
+
+/// Formats an expression in infix notation with minimal parentheses.
+///
+/// Empty lists print as constants and single-item lists print as their item.
+pub struct Infix<'a, T> {
+    expr: &'a Expr<T>,
+    syntax: Syntax,
+}
+
+#[must_use]
+pub fn display<T>(expr: &Expr<T>, syntax: Syntax) -> Infix<'_, T> {
+    Infix { expr, syntax }
+}
+
+impl<T> fmt::Display for Infix<'_, T>
+where
+    T: fmt::Display,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let tokens = self.syntax.style.tokens();
+        fmt_infix(
+            f,
+            self.expr,
+            self.syntax.precedence,
+            tokens,
+            None,
+            &|f, x| write!(f, "{x}"),
+        )
+    }
+}
+
+type FmtVar<'a, T> = dyn Fn(&mut fmt::Formatter<'_>, &T) -> fmt::Result + 'a;
+
+/// The enclosing operator of a sub-expression.
+#[derive(Clone, Copy)]
+pub(crate) enum Parent {
+    List {
+        is_and: bool,
+        prec: u8,
+    },
+    /// Binary lists under `not` always need parentheses.
+    Not,
+}
+
+/// `parent` is the enclosing operator, if any.
+pub(crate) fn fmt_infix<T>(
+    f: &mut fmt::Formatter<'_>,
+    expr: &Expr<T>,
+    precedence: Precedence,
+    tokens: &Tokens,
+    parent: Option<Parent>,
+    fmt_var: &FmtVar<'_, T>,
+) -> fmt::Result {
+    match expr {
+        Expr::Any(Any(list)) | Expr::All(All(list)) => {
+            let is_and = expr.is_all();
+            match list.as_slice() {
+                [] => f.write_str(if is_and { tokens.true_ } else { tokens.false_ }),
+                [x] => fmt_infix(f, x, precedence, tokens, parent, fmt_var),
+                _ => {
+                    let (op, prec) = if is_and {
+                        (tokens.and, precedence.and)
+                    } else {
+                        (tokens.or, precedence.or)
+                    };
+                    let paren = match parent {
+                        None => false,
+                        Some(Parent::Not) => true,
+                        Some(Parent::List {
+                            is_and: p_and,
+                            prec: p_prec,
+                        }) => prec < p_prec || (prec == p_prec && p_and != is_and),
+                    };
+                    if paren {
+                        f.write_str("(")?;
+                    }
+                    for (i, x) in list.iter().enumerate() {
+                        if i != 0 {
+                            f.write_str(op)?;
+                        }
+                        let parent = Parent::List { is_and, prec };
+                        fmt_infix(f, x, precedence, tokens, Some(parent), fmt_var)?;
+                    }
+                    if paren {
+                        f.write_str(")")?;
+                    }
+                    Ok(())
+                }
+            }
+        }
+        Expr::Not(Not(not)) => {
+            f.write_str(tokens.not)?;
+            fmt_infix(f, not, precedence, tokens, Some(Parent::Not), fmt_var)
+        }
+        Expr::Var(Var(x)) => fmt_var(f, x),
+        Expr::Const(b) => f.write_str(if *b { tokens.true_ } else { tokens.false_ }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::{all, any, expr, not, var};
+
+    fn v(s: &str) -> Var<String> {
+        var(s.to_owned())
+    }
+
+    #[test]
+    fn parse_symbols() {
+        let x = parse("a && (b || !c)").unwrap();
+        assert_eq!(x, expr(all((v("a"), any((v("b"), not(v("c"))))))));
+
+        let x = parse("a & b | ~c & true").unwrap();
+        assert_eq!(
+            x,
+            expr(any((
+                all((v("a"), v("b"))),
+                all((not(v("c")), Expr::Const(true)))
+            )))
+        );
+    }
+
+    #[test]
+    fn parse_keywords() {
+        let x = parse("not a or b and not (c or d)").unwrap();
+        assert_eq!(
+            x,
+            expr(any((
+                not(v("a")),
+                all((v("b"), not(any((v("c"), v("d"))))))
+            )))
+        );
+
+        let x = parse("android and order").unwrap();
+        assert_eq!(x, expr(all((v("android"), v("order")))));
+    }
+
+    #[test]
+    fn parse_precedence() {
+        let syntax = Syntax {
+            precedence: Precedence { and: 1, or: 2 },
+            ..Syntax::default()
+        };
+        let x = parse_with("a && b || c", syntax).unwrap();
+        assert_eq!(x, expr(all((v("a"), any((v("b"), v("c")))))));
+
+        let syntax = Syntax {
+            precedence: Precedence { and: 1, or: 1 },
+            ..Syntax::default()
+        };
+        let x = parse_with("a || b && c", syntax).unwrap();
+        assert_eq!(x, expr(all((any((v("a"), v("b"))), v("c")))));
+    }
+
+    #[test]
+    fn parse_error() {
+        assert!(parse("a &&").is_err());
+        assert!(parse("(a || b").is_err());
+        assert!(parse("a b").is_err());
+        assert!(parse("and").is_err());
+    }
+
+    #[test]
+    fn print_minimal_parens() {
+        let cases = [
+            ("a && (b || !c)", "a && (b || !c)"),
+            ("(a && b) || c", "a && b || c"),
+            ("!(a || b) && !!c", "!(a || b) && !!c"),
+            ("a || (b || c)", "a || b || c"),
+        ];
+        for (input, expected) in cases {
+            let x = parse(input).unwrap();
+            assert_eq!(display(&x, Syntax::default()).to_string(), expected);
+        }
+
+        let syntax = Syntax {
+            precedence: Precedence { and: 1, or: 1 },
+            style: Style::Keyword,
+        };
+        let x = parse("a && b || c && d").unwrap();
+        assert_eq!(display(&x, syntax).to_string(), "(a and b) or (c and d)");
+        assert_eq!(
+            parse_with(&display(&x, syntax).to_string(), syntax).unwrap(),
+            x
+        );
+    }
+
+    #[test]
+    fn max_precedence() {
+        let syntax = Syntax {
+            precedence: Precedence { and: 255, or: 255 },
+            ..Syntax::default()
+        };
+        let x = parse_with("a && b && c", syntax).unwrap();
+        assert_eq!(x, expr(all((v("a"), v("b"), v("c")))));
+
+        let x = parse_with("!(a || b)", syntax).unwrap();
+        assert_eq!(display(&x, syntax).to_string(), "!(a || b)");
+    }
+
+    #[test]
+    fn depth_limit() {
+        let nested = |n| format!("{}a{}", "(!".repeat(n / 2), ")".repeat(n / 2));
+        assert!(parse(&nested(MAX_DEPTH)).is_ok());
+        assert!(parse(&nested(MAX_DEPTH + 2)).is_err());
+        assert!(parse(&"!".repeat(1_000_000)).is_err());
+    }
+}
@@ -1,3 +1,6 @@
+#![cfg_attr(docsrs, feature(doc_cfg))]
+
+mod parsing;
 mod utils;
 
 pub mod ast;
@@ -9,3 +12,29 @@ pub mod visit_mut;
 pub mod transforms;
 
 pub mod cfg;
+
+pub mod infix;
+
+pub mod cpp;
+
+pub mod spdx;
+
+pub mod dimacs;
+
+pub mod smtlib;
+
+pub mod dot;
+
+pub mod sexpr;
+
+pub mod pretty;
+
+pub mod explain;
+
+pub mod truth_table;
+
+pub mod count;
+
+pub mod batch;
+
+pub mod compile;
@@ -0,0 +1,72 @@
+use std::backtrace::Backtrace;
+use std::ops::Not as _;
+use std::panic::Location;
+
+#[derive(Debug)]
+pub struct Error<'p> {
+    pub input: &'p str,
+    pub location: &'static Location<'static>,
+    pub backtrace: Backtrace,
+}
+
+pub type Result<'p, T, E = Error<'p>> = std::result::Result<T, E>;
+
+/// The maximum nesting depth accepted by the parsers.
+///
+/// Bounds the recursion of the parsers, so untrusted input cannot overflow the stack.
+pub const MAX_DEPTH: usize = 256;
+
+macro_rules! ensure {
+    ($s:expr, $cond:expr) => {
+        if !$cond {
+            return Err($crate::parsing::Error {
+                input: $s,
+                location: ::std::panic::Location::caller(),
+                backtrace: ::std::backtrace::Backtrace::capture(),
+            });
+        }
+    };
+}
+
+pub(crate) use ensure;
+
+pub fn take_while1<'p>(s: &mut &'p str, f: impl Fn(char) -> bool) -> Result<'p, &'p str> {
+    let end = s
+        .char_indices()
+        .find_map(|(i, c)| f(c).not().then_some(i))
+        .unwrap_or(s.len());
+
+    ensure!(s, end > 0);
+
+    let (ans, rest) = s.split_at(end);
+    *s = rest;
+    Ok(ans)
+}
+
+pub fn consume_tag<'p>(s: &mut &'p str, tag: &str) -> Result<'p, &'p str> {
+    ensure!(s, s.starts_with(tag));
+    let (ans, rest) = s.split_at(tag.len());
+    *s = rest;
+    Ok(ans)
+}
+
+pub fn skip_space0(s: &mut &str) {
+    *s = s.trim_start();
+}
+
+pub fn skip_tag<'p>(s: &mut &'p str, tag: &str) -> Option<&'p str> {
+    if s.starts_with(tag) {
+        let (ans, rest) = s.split_at(tag.len());
+        *s = rest;
+        Some(ans)
+    } else {
+        None
+    }
+}
+
+/// Parses an ASCII identifier, `[A-Za-z_][A-Za-z0-9_]*`.
+// TODO: unicode identifier
+pub fn parse_identifier<'p>(s: &mut &'p str) -> Result<'p, &'p str> {
+    ensure!(s, s.starts_with(|c| matches!(c, 'a'..='z'|'A'..='Z'|'_')));
+    take_while1(s, |c| matches!(c, 'a'..='z'|'A'..='Z'|'0'..='9'|'_'))
+}
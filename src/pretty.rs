@@ -0,0 +1,269 @@
+use crate::ast::{Expr, Node, fold_ref};
+
+use std::fmt;
+use std::mem;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PrettyConfig {
+    /// Maximum line width, exceeded only by atoms which do not fit on their own.
+    pub width: usize,
+    /// Indentation of the items of a broken list.
+    pub indent: usize,
+}
+
+impl Default for PrettyConfig {
+    fn default() -> Self {
+        Self {
+            width: 100,
+            indent: 4,
+        }
+    }
+}
+
+/// A document in the style of Wadler's "A prettier printer".
+enum Doc {
+    Text(String),
+    /// A space when flat, a newline when broken.
+    Line,
+    /// Nothing when flat, a newline when broken.
+    SoftLine,
+    /// Text printed only when broken.
+    IfBreak(&'static str),
+    Nest(usize, Box<Doc>),
+    /// Printed flat if it fits in the remaining width.
+    Group(Box<Doc>),
+    Concat(Vec<Doc>),
+}
+
+impl Doc {
+    fn take_children(&mut self, stack: &mut Vec<Doc>) {
+        match self {
+            Doc::Nest(_, doc) | Doc::Group(doc) => {
+                stack.push(mem::replace(&mut **doc, Doc::SoftLine));
+            }
+            Doc::Concat(list) => stack.append(list),
+            Doc::Text(_) | Doc::Line | Doc::SoftLine | Doc::IfBreak(_) => {}
+        }
+    }
+}
+
+/// Documents are as deep as the expression, so they are dropped with an explicit stack.
+impl Drop for Doc {
+    fn drop(&mut self) {
+        let mut stack = Vec::new();
+        self.take_children(&mut stack);
+        while let Some(mut doc) = stack.pop() {
+            doc.take_children(&mut stack);
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    Flat,
+    Break,
+}
+
+fn text(s: impl Into<String>) -> Doc {
+    Doc::Text(s.into())
+}
+
+fn to_doc<T: fmt::Display>(expr: &Expr<T>, config: PrettyConfig) -> Doc {
+    fold_ref(expr, &mut |node| match node {
+        Node::Any(list) => list_doc("any", list, config),
+        Node::All(list) => list_doc("all", list, config),
+        Node::Not(not) => Doc::Concat(vec![text("not("), not, text(")")]),
+        Node::Var(x) => text(x.to_string()),
+        Node::Const(b) => text(b.to_string()),
+    })
+}
+
+/// `name(a, b)` when flat, one item per line with a trailing comma when broken, like rustfmt.
+/// A single item is never broken onto its own line.
+fn list_doc(name: &str, mut list: Vec<Doc>, config: PrettyConfig) -> Doc {
+    if list.len() == 1 {
+        list.insert(0, text(format!("{name}(")));
+        list.push(text(")"));
+        return Doc::Concat(list);
+    }
+    if list.is_empty() {
+        return text(format!("{name}()"));
+    }
+
+    let mut items = vec![Doc::SoftLine];
+    for (i, x) in list.into_iter().enumerate() {
+        if i != 0 {
+            items.push(text(","));
+            items.push(Doc::Line);
+        }
+        items.push(x);
+    }
+    items.push(Doc::IfBreak(","));
+
+    Doc::Group(Box::new(Doc::Concat(vec![
+        text(format!("{name}(")),
+        Doc::Nest(config.indent, Box::new(Doc::Concat(items))),
+        Doc::SoftLine,
+        text(")"),
+    ])))
+}
+
+/// Returns whether the first line of `doc` printed flat, followed by `rest`, fits in `rem` columns.
+///
+/// `rest` is the printer's stack, which is walked from the top without being copied.
+fn fits(mut rem: usize, rest: &[(usize, Mode, &Doc)], doc: &Doc) -> bool {
+    let mut rest = rest.iter().rev().map(|&(_, mode, doc)| (mode, doc));
+    let mut stack = vec![(Mode::Flat, doc)];
+
+    while let Some((mode, doc)) = stack.pop().or_else(|| rest.next()) {
+        let width = match doc {
+            Doc::Text(s) => s.chars().count(),
+            Doc::Line | Doc::SoftLine if mode == Mode::Break => return true,
+            Doc::Line => 1,
+            Doc::SoftLine => 0,
+            Doc::IfBreak(s) => {
+                if mode == Mode::Break {
+                    s.chars().count()
+                } else {
+                    0
+                }
+            }
+            Doc::Nest(_, doc) | Doc::Group(doc) => {
+                stack.push((mode, doc));
+                0
+            }
+            Doc::Concat(list) => {
+                stack.extend(list.iter().rev().map(|d| (mode, d)));
+                0
+            }
+        };
+        match rem.checked_sub(width) {
+            Some(r) => rem = r,
+            None => return false,
+        }
+    }
+    true
+}
+
+fn layout(doc: &Doc, width: usize) -> String {
+    let mut out = String::new();
+    let mut col = 0;
+    let mut stack = vec![(0, Mode::Break, doc)];
+
+    while let Some((indent, mode, doc)) = stack.pop() {
+        match doc {
+            Doc::Text(s) => {
+                out.push_str(s);
+                col += s.chars().count();
+            }
+            Doc::Line | Doc::SoftLine if mode == Mode::Break => {
+                out.push('\n');
+                out.extend(std::iter::repeat_n(' ', indent));
+                col = indent;
+            }
+            Doc::Line => {
+                out.push(' ');
+                col += 1;
+            }
+            Doc::SoftLine => {}
+            Doc::IfBreak(s) => {
+                if mode == Mode::Break {
+                    out.push_str(s);
+                    col += s.chars().count();
+                }
+            }
+            Doc::Nest(n, doc) => stack.push((indent + n, mode, doc)),
+            Doc::Group(doc) => {
+                let rem = width.saturating_sub(col);
+                let mode = if fits(rem, &stack, doc) {
+                    Mode::Flat
+                } else {
+                    Mode::Break
+                };
+                stack.push((indent, mode, doc));
+            }
+            Doc::Concat(list) => stack.extend(list.iter().rev().map(|d| (indent, mode, d))),
+        }
+    }
+
+    out
+}
+
+/// Formats an expression on multiple lines, breaking `any(..)` and `all(..)` lists
+/// that do not fit in the configured width.
+///
+/// It works for generic expressions and cfg expressions alike.
+#[must_use]
+pub fn pretty<T>(expr: &Expr<T>, config: PrettyConfig) -> String
+where
+    T: fmt::Display,
+{
+    layout(&to_doc(expr, config), config.width)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::{all, any, expr, not, var};
+    use crate::cfg::ast::{flag, target_arch, target_os};
+
+    #[test]
+    fn flat_when_fits() {
+        let x = expr(any((var("a"), all((var("b"), not(var("c")))))));
+        assert_eq!(pretty(&x, PrettyConfig::default()), x.to_string());
+    }
+
+    #[test]
+    fn break_cfg() {
+        let x = crate::cfg::ast::expr(all((
+            flag("unix"),
+            not(any((
+                target_os("linux"),
+                target_os("android"),
+                target_os("freebsd"),
+            ))),
+            any((target_arch("x86_64"), target_arch("aarch64"))),
+        )));
+        let config = PrettyConfig {
+            width: 60,
+            indent: 4,
+        };
+        let expected = r#"all(
+    unix,
+    not(any(
+        target_os = "linux",
+        target_os = "android",
+        target_os = "freebsd",
+    )),
+    any(target_arch = "x86_64", target_arch = "aarch64"),
+)"#;
+        assert_eq!(pretty(&x, config), expected);
+    }
+
+    #[test]
+    fn indent() {
+        let x = expr(any((var("alpha"), var("beta"), var("gamma"))));
+        let config = PrettyConfig {
+            width: 10,
+            indent: 2,
+        };
+        assert_eq!(pretty(&x, config), "any(\n  alpha,\n  beta,\n  gamma,\n)");
+    }
+
+    #[test]
+    fn deeply_nested() {
+        let mut x = expr(var("a"));
+        for _ in 0..100_000 {
+            x = expr(all((not(x), var("b"))));
+        }
+        let config = PrettyConfig {
+            width: 40,
+            indent: 0,
+        };
+        let text = pretty(&x, config);
+        assert!(text.starts_with("all(\nnot(all(\nnot(all(\n"));
+        assert!(text.ends_with(")),\nb,\n)),\nb,\n)"));
+        assert!(text.lines().all(|line| line.len() <= 40));
+        x.drop_iterative();
+    }
+}
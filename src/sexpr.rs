@@ -0,0 +1,328 @@
+#![allow(clippy::missing_errors_doc)]
+
+use crate::ast::{All, Any, Expr, Not, Var};
+use crate::parsing::{consume_tag, ensure, skip_space0, skip_tag, take_while1};
+
+use std::fmt::{self, Write as _};
+
+pub use crate::parsing::{Error, MAX_DEPTH, Result};
+
+/// Parses an s-expression like `(or a (and b (not c)))`.
+///
+/// Atoms are bare symbols or double-quoted strings with `\"` and `\\` escapes.
+/// The bare symbols `true` and `false` are constants.
+pub fn parse(mut s: &str) -> Result<'_, Expr<String>> {
+    let s = &mut s;
+    skip_space0(s);
+    let expr = parse_expr(s, 0)?;
+    skip_space0(s);
+    ensure!(s, s.is_empty());
+    Ok(expr)
+}
+
+fn is_symbol_char(c: char) -> bool {
+    !c.is_whitespace() && !matches!(c, '(' | ')' | '"')
+}
+
+fn parse_expr<'p>(s: &mut &'p str, depth: usize) -> Result<'p, Expr<String>> {
+    if s.starts_with('(') {
+        ensure!(s, depth < MAX_DEPTH);
+        consume_tag(s, "(")?;
+        skip_space0(s);
+        let op = take_while1(s, is_symbol_char)?;
+        ensure!(s, matches!(op, "or" | "and" | "not"));
+
+        let mut list = Vec::new();
+        loop {
+            skip_space0(s);
+            if skip_tag(s, ")").is_some() {
+                break;
+            }
+            ensure!(s, !s.is_empty());
+            list.push(parse_expr(s, depth + 1)?);
+        }
+
+        return match op {
+            "or" => Ok(Expr::Any(Any(list))),
+            "and" => Ok(Expr::All(All(list))),
+            _ => {
+                ensure!(s, list.len() == 1);
+                Ok(Expr::Not(Not(Box::new(list.pop().unwrap()))))
+            }
+        };
+    }
+
+    if s.starts_with('"') {
+        return Ok(Expr::Var(Var(parse_string(s)?)));
+    }
+
+    match take_while1(s, is_symbol_char)? {
+        "true" => Ok(Expr::Const(true)),
+        "false" => Ok(Expr::Const(false)),
+        sym => Ok(Expr::Var(Var(sym.to_owned()))),
+    }
+}
+
+fn parse_string<'p>(s: &mut &'p str) -> Result<'p, String> {
+    consume_tag(s, "\"")?;
+    let mut ans = String::new();
+    loop {
+        ensure!(s, !s.is_empty());
+        if skip_tag(s, "\"").is_some() {
+            return Ok(ans);
+        }
+        if skip_tag(s, "\\").is_some() {
+            ensure!(s, s.starts_with(['"', '\\']));
+        }
+        let c = s.chars().next().unwrap();
+        ans.push(c);
+        *s = &s[c.len_utf8()..];
+    }
+}
+
+fn write_atom(out: &mut String, atom: &str) {
+    let is_symbol =
+        !atom.is_empty() && atom.chars().all(is_symbol_char) && !matches!(atom, "true" | "false");
+    if is_symbol {
+        out.push_str(atom);
+    } else {
+        out.push('"');
+        for c in atom.chars() {
+            if matches!(c, '"' | '\\') {
+                out.push('\\');
+            }
+            out.push(c);
+        }
+        out.push('"');
+    }
+}
+
+fn head<T>(expr: &Expr<T>) -> Option<(&'static str, &[Expr<T>])> {
+    match expr {
+        Expr::Any(Any(list)) => Some(("or", list)),
+        Expr::All(All(list)) => Some(("and", list)),
+        Expr::Not(Not(not)) => Some(("not", std::slice::from_ref(&**not))),
+        Expr::Var(_) | Expr::Const(_) => None,
+    }
+}
+
+fn write_flat<T: fmt::Display>(out: &mut String, expr: &Expr<T>) {
+    enum Item<'e, T> {
+        Expr(&'e Expr<T>),
+        Str(&'static str),
+    }
+
+    let mut stack = vec![Item::Expr(expr)];
+    while let Some(item) = stack.pop() {
+        match item {
+            Item::Str(s) => out.push_str(s),
+            Item::Expr(Expr::Var(Var(x))) => write_atom(out, &x.to_string()),
+            Item::Expr(Expr::Const(b)) => write!(out, "{b}").unwrap(),
+            Item::Expr(expr) => {
+                let Some((op, list)) = head(expr) else {
+                    unreachable!()
+                };
+                write!(out, "({op}").unwrap();
+                stack.push(Item::Str(")"));
+                for x in list.iter().rev() {
+                    stack.push(Item::Expr(x));
+                    stack.push(Item::Str(" "));
+                }
+            }
+        }
+    }
+}
+
+/// Formats an expression as a single-line s-expression.
+#[must_use]
+pub fn to_sexpr<T>(expr: &Expr<T>) -> String
+where
+    T: fmt::Display,
+{
+    let mut out = String::new();
+    write_flat(&mut out, expr);
+    out
+}
+
+/// Formats an expression as an s-expression, breaking lists that do not fit in `width` columns
+/// with one item per line, indented by two spaces.
+#[must_use]
+pub fn to_sexpr_pretty<T>(expr: &Expr<T>, width: usize) -> String
+where
+    T: fmt::Display,
+{
+    let mut out = String::new();
+    let sizes = flat_sizes(expr);
+    write_pretty(&mut out, expr, &sizes, width);
+    out
+}
+
+/// Returns the flat width and the node count of every subtree in pre-order, computed bottom-up.
+fn flat_sizes<T: fmt::Display>(expr: &Expr<T>) -> Vec<(usize, usize)> {
+    let mut ans = Vec::new();
+    let mut parents = Vec::new();
+    let mut stack = vec![(expr, None)];
+    while let Some((expr, parent)) = stack.pop() {
+        let slot = ans.len();
+        let width = if let Some((op, list)) = head(expr) {
+            stack.extend(list.iter().rev().map(|x| (x, Some(slot))));
+            op.len() + 2
+        } else {
+            let mut atom = String::new();
+            write_flat(&mut atom, expr);
+            atom.len()
+        };
+        ans.push((width, 1));
+        parents.push(parent);
+    }
+
+    // the children of a node follow it in pre-order, so they are complete when it is reached
+    for slot in (1..ans.len()).rev() {
+        let Some(parent) = parents[slot] else {
+            unreachable!()
+        };
+        let (width, count) = ans[slot];
+        ans[parent].0 += 1 + width;
+        ans[parent].1 += count;
+    }
+    ans
+}
+
+/// Writes the subtrees with an explicit stack, advancing a pre-order index into `sizes`.
+fn write_pretty<T: fmt::Display>(
+    out: &mut String,
+    expr: &Expr<T>,
+    sizes: &[(usize, usize)],
+    width: usize,
+) {
+    enum Item<'e, T> {
+        Expr(&'e Expr<T>, usize),
+        Line(usize),
+        Close,
+    }
+
+    let mut pos = 0;
+    let mut stack = vec![Item::Expr(expr, 0)];
+    while let Some(item) = stack.pop() {
+        let (expr, indent) = match item {
+            Item::Expr(expr, indent) => (expr, indent),
+            Item::Line(indent) => {
+                out.push('\n');
+                out.extend(std::iter::repeat_n(' ', indent));
+                continue;
+            }
+            Item::Close => {
+                out.push(')');
+                continue;
+            }
+        };
+
+        let (flat, count) = sizes[pos];
+        let Some((op, list)) = head(expr).filter(|_| indent + flat > width) else {
+            write_flat(out, expr);
+            pos += count;
+            continue;
+        };
+
+        pos += 1;
+        write!(out, "({op}").unwrap();
+        stack.push(Item::Close);
+        for x in list.iter().rev() {
+            stack.push(Item::Expr(x, indent + 2));
+            stack.push(Item::Line(indent + 2));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::{all, any, const_, expr, not, var};
+
+    fn v(s: &str) -> Var<String> {
+        var(s.to_owned())
+    }
+
+    #[test]
+    fn parse_nested() {
+        let x = parse(" (or a (and b (not c)) (and) true \"target_os = \\\"linux\\\"\") ").unwrap();
+        let expected = expr(any((
+            v("a"),
+            all((v("b"), not(v("c")))),
+            all(()),
+            const_(true),
+            v("target_os = \"linux\""),
+        )));
+        assert_eq!(x, expected);
+        assert_eq!(parse(&to_sexpr(&x)).unwrap(), x);
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert!(parse("(or a").is_err());
+        assert!(parse("(xor a b)").is_err());
+        assert!(parse("(not a b)").is_err());
+        assert!(parse("\"abc").is_err());
+        assert!(parse("\"a\\nb\"").is_err());
+        assert!(parse("a b").is_err());
+    }
+
+    #[test]
+    fn print() {
+        let x = expr(any((
+            v("a"),
+            all((v("b"), not(v("c")))),
+            v("true"),
+            v("x y"),
+        )));
+        assert_eq!(to_sexpr(&x), r#"(or a (and b (not c)) "true" "x y")"#);
+    }
+
+    #[test]
+    fn print_pretty() {
+        let x = expr(any((
+            all((v("alpha"), v("beta"))),
+            not(all((v("gamma"), v("delta"), v("epsilon")))),
+        )));
+        let expected = "\
+(or
+  (and alpha beta)
+  (not
+    (and gamma delta epsilon)))";
+        assert_eq!(to_sexpr_pretty(&x, 30), expected);
+        assert_eq!(to_sexpr_pretty(&x, 80), to_sexpr(&x));
+    }
+
+    #[test]
+    fn flat_widths() {
+        let x = expr(any((v("ab"), not(v("x y")), all(()))));
+        let sizes = flat_sizes(&x);
+        assert_eq!(sizes[0], (to_sexpr(&x).len(), 5));
+        assert_eq!(sizes[2], ("(not \"x y\")".len(), 2));
+    }
+
+    #[test]
+    fn depth_limit() {
+        let nested = |n| format!("{}a{}", "(not ".repeat(n), ")".repeat(n));
+        assert!(parse(&nested(MAX_DEPTH)).is_ok());
+        assert!(parse(&nested(MAX_DEPTH + 1)).is_err());
+        assert!(parse(&nested(1_000_000)).is_err());
+    }
+
+    #[test]
+    fn deeply_nested() {
+        let mut x = expr(v("a"));
+        for _ in 0..100_000 {
+            x = expr(all((not(x), const_(true))));
+        }
+        let text = to_sexpr(&x);
+        assert!(text.starts_with("(and (not (and (not "));
+        assert_eq!(text.len(), 100_000 * "(and (not ) true)".len() + 1);
+        assert_eq!(flat_sizes(&x)[0], (text.len(), 3 * 100_000 + 1));
+
+        assert_eq!(to_sexpr_pretty(&x, text.len()), text);
+        let text = to_sexpr_pretty(&x, text.len() - 1);
+        assert!(text.starts_with("(and\n  (not (and (not "));
+        x.drop_iterative();
+    }
+}
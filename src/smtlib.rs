@@ -0,0 +1,201 @@
+use crate::ast::{All, Any, Expr, Not, Var};
+
+use std::collections::HashMap;
+use std::fmt::{self, Write as _};
+use std::hash::Hash;
+
+/// Numbers the distinct variables as `v0`, `v1`, ..., so that names cannot clash with each other
+/// or with SMT-LIB keywords, whatever their `Display`.
+struct Symbols<'a, T> {
+    vars: Vec<&'a T>,
+    index: HashMap<&'a T, usize>,
+    names: Vec<String>,
+}
+
+impl<'a, T> Symbols<'a, T>
+where
+    T: Eq + Hash + fmt::Display,
+{
+    fn new(exprs: &[&'a Expr<T>]) -> Self {
+        let mut vars: Vec<&T> = Vec::new();
+        let mut index = HashMap::new();
+        for x in exprs.iter().flat_map(|e| e.vars()) {
+            index.entry(x).or_insert_with(|| {
+                vars.push(x);
+                vars.len() - 1
+            });
+        }
+        let names = (0..vars.len()).map(|i| format!("v{i}")).collect();
+        Self { vars, index, names }
+    }
+
+    fn get(&self, x: &T) -> &str {
+        &self.names[self.index[x]]
+    }
+
+    fn write_header(&self, out: &mut String) {
+        out.push_str("(set-logic QF_UF)\n");
+        for (name, var) in self.names.iter().zip(&self.vars) {
+            let comment = var.to_string().replace(['\n', '\r'], " ");
+            writeln!(out, "(declare-const {name} Bool) ; {comment}").unwrap();
+        }
+    }
+
+    fn write_term(&self, out: &mut String, expr: &Expr<T>) {
+        enum Item<'e, T> {
+            Expr(&'e Expr<T>),
+            Str(&'static str),
+        }
+
+        let mut stack = vec![Item::Expr(expr)];
+        while let Some(item) = stack.pop() {
+            let expr = match item {
+                Item::Expr(expr) => expr,
+                Item::Str(s) => {
+                    out.push_str(s);
+                    continue;
+                }
+            };
+            match expr {
+                Expr::Any(Any(list)) | Expr::All(All(list)) => {
+                    let (op, empty) = if expr.is_all() {
+                        ("and", "true")
+                    } else {
+                        ("or", "false")
+                    };
+                    match list.as_slice() {
+                        [] => out.push_str(empty),
+                        [x] => stack.push(Item::Expr(x)),
+                        _ => {
+                            write!(out, "({op}").unwrap();
+                            stack.push(Item::Str(")"));
+                            for x in list.iter().rev() {
+                                stack.push(Item::Expr(x));
+                                stack.push(Item::Str(" "));
+                            }
+                        }
+                    }
+                }
+                Expr::Not(Not(not)) => {
+                    out.push_str("(not ");
+                    stack.push(Item::Str(")"));
+                    stack.push(Item::Expr(not));
+                }
+                Expr::Var(Var(x)) => out.push_str(self.get(x)),
+                Expr::Const(b) => write!(out, "{b}").unwrap(),
+            }
+        }
+    }
+}
+
+/// Generates an SMT-LIB 2 script asserting `expr`. It is `sat` iff `expr` is satisfiable.
+#[must_use]
+pub fn to_smtlib<T>(expr: &Expr<T>) -> String
+where
+    T: Eq + Hash + fmt::Display,
+{
+    let symbols = Symbols::new(&[expr]);
+    let mut out = String::new();
+    symbols.write_header(&mut out);
+    out.push_str("(assert ");
+    symbols.write_term(&mut out, expr);
+    out.push_str(")\n(check-sat)\n");
+    out
+}
+
+/// Generates an SMT-LIB 2 script asserting `(not (= lhs rhs))`. It is `unsat` iff the expressions are equivalent.
+#[must_use]
+pub fn equivalence_query<T>(lhs: &Expr<T>, rhs: &Expr<T>) -> String
+where
+    T: Eq + Hash + fmt::Display,
+{
+    let symbols = Symbols::new(&[lhs, rhs]);
+    let mut out = String::new();
+    symbols.write_header(&mut out);
+    out.push_str("(assert (not (= ");
+    symbols.write_term(&mut out, lhs);
+    out.push(' ');
+    symbols.write_term(&mut out, rhs);
+    out.push_str(")))\n(check-sat)\n");
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::{all, any, const_, expr, not, var};
+
+    #[test]
+    fn assert_script() {
+        let x = expr(any((
+            var("a"),
+            all((var("b"), not(var("c")))),
+            all(()),
+            any((var("a"),)),
+        )));
+        let expected = "\
+(set-logic QF_UF)
+(declare-const v0 Bool) ; a
+(declare-const v1 Bool) ; b
+(declare-const v2 Bool) ; c
+(assert (or v0 (and v1 (not v2)) true v0))
+(check-sat)
+";
+        assert_eq!(to_smtlib(&x), expected);
+    }
+
+    #[test]
+    fn equivalence_script() {
+        let lhs = expr(not(any((var("x"), var("y")))));
+        let rhs = expr(all((not(var("x")), not(var("y")), const_(true))));
+        let expected = "\
+(set-logic QF_UF)
+(declare-const v0 Bool) ; x
+(declare-const v1 Bool) ; y
+(assert (not (= (not (or v0 v1)) (and (not v0) (not v1) true))))
+(check-sat)
+";
+        assert_eq!(equivalence_query(&lhs, &rhs), expected);
+    }
+
+    #[test]
+    fn generated_symbols() {
+        #[derive(PartialEq, Eq, Hash)]
+        struct Named(u32, &'static str);
+
+        impl fmt::Display for Named {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.write_str(self.1)
+            }
+        }
+
+        let x = expr(any((
+            var(Named(0, "xor")),
+            var(Named(1, "=>")),
+            var(Named(2, "a|b\nc")),
+            var(Named(3, "xor")),
+        )));
+        let expected = "\
+(set-logic QF_UF)
+(declare-const v0 Bool) ; xor
+(declare-const v1 Bool) ; =>
+(declare-const v2 Bool) ; a|b c
+(declare-const v3 Bool) ; xor
+(assert (or v0 v1 v2 v3))
+(check-sat)
+";
+        assert_eq!(to_smtlib(&x), expected);
+    }
+
+    #[test]
+    fn deeply_nested() {
+        let mut x = expr(var("a"));
+        for _ in 0..100_000 {
+            x = expr(all((not(x), const_(true))));
+        }
+        let script = to_smtlib(&x);
+        assert!(script.contains("(assert (and (not (and (not "));
+        assert!(script.ends_with(&format!("v0{})\n(check-sat)\n", ") true)".repeat(100_000))));
+        x.drop_iterative();
+    }
+}
@@ -0,0 +1,128 @@
+pub use crate::ast::{All, Any, Not, Var};
+
+use crate::eval::eval_with;
+use crate::infix::{Precedence, Tokens, fmt_infix};
+
+use std::fmt;
+
+pub type Expr = crate::ast::Expr<LicenseTerm>;
+
+/// A license with an optional `+` and `WITH` exception, e.g. `GPL-2.0-only WITH Classpath-exception-2.0`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct LicenseTerm {
+    pub license: String,
+    pub or_later: bool,
+    pub exception: Option<String>,
+}
+
+impl From<LicenseTerm> for Expr {
+    fn from(x: LicenseTerm) -> Self {
+        Expr::Var(Var(x))
+    }
+}
+
+pub fn license(s: impl Into<String>) -> LicenseTerm {
+    LicenseTerm {
+        license: s.into(),
+        or_later: false,
+        exception: None,
+    }
+}
+
+pub fn with_exception(s: impl Into<String>, e: impl Into<String>) -> LicenseTerm {
+    LicenseTerm {
+        license: s.into(),
+        or_later: false,
+        exception: Some(e.into()),
+    }
+}
+
+impl fmt::Display for LicenseTerm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.license)?;
+        if self.or_later {
+            write!(f, "+")?;
+        }
+        if let Some(exception) = &self.exception {
+            write!(f, " WITH {exception}")?;
+        }
+        Ok(())
+    }
+}
+
+const TOKENS: Tokens = Tokens {
+    and: " AND ",
+    or: " OR ",
+    not: "",
+    true_: "",
+    false_: "",
+};
+
+const PRECEDENCE: Precedence = Precedence { and: 2, or: 1 };
+
+struct Canonical<'a>(&'a Expr);
+
+impl fmt::Display for Canonical<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt_infix(f, self.0, PRECEDENCE, &TOKENS, None, &|f, x| {
+            write!(f, "{x}")
+        })
+    }
+}
+
+fn is_spdx(expr: &Expr) -> bool {
+    match expr {
+        Expr::Any(Any(list)) | Expr::All(All(list)) => !list.is_empty() && list.iter().all(is_spdx),
+        Expr::Var(_) => true,
+        Expr::Not(_) | Expr::Const(_) => false,
+    }
+}
+
+/// Formats an expression as a canonical SPDX license expression with minimal parentheses.
+///
+/// Returns `None` if the expression contains `not`, a constant or an empty list, which SPDX cannot express.
+#[must_use]
+pub fn to_spdx(expr: &Expr) -> Option<String> {
+    is_spdx(expr).then(|| Canonical(expr).to_string())
+}
+
+/// Returns whether the expression is satisfied by the allowed terms, e.g. `["MIT", "Apache-2.0"]`.
+///
+/// A term is allowed if its canonical form is in the list.
+#[must_use]
+pub fn is_allowed(expr: &Expr, allowed: &[&str]) -> bool {
+    eval_with(expr, &|term| allowed.contains(&term.to_string().as_str()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::{all, any, const_, expr, not};
+
+    #[test]
+    fn spdx_string() {
+        let x = expr(all((
+            any((license("MIT"), license("Apache-2.0"))),
+            with_exception("GPL-2.0-only", "Classpath-exception-2.0"),
+        )));
+        let expected = "(MIT OR Apache-2.0) AND GPL-2.0-only WITH Classpath-exception-2.0";
+        assert_eq!(to_spdx(&x).unwrap(), expected);
+
+        assert!(to_spdx(&expr(not(license("MIT")))).is_none());
+        assert!(to_spdx(&expr(any((license("MIT"), const_(true))))).is_none());
+    }
+
+    #[test]
+    fn allowed() {
+        let x = expr(any((
+            license("MIT"),
+            with_exception("GPL-2.0-only", "Classpath-exception-2.0"),
+        )));
+        assert!(is_allowed(&x, &["MIT"]));
+        assert!(is_allowed(
+            &x,
+            &["GPL-2.0-only WITH Classpath-exception-2.0"]
+        ));
+        assert!(!is_allowed(&x, &["GPL-2.0-only"]));
+    }
+}
@@ -0,0 +1,173 @@
+#![allow(clippy::missing_errors_doc)]
+
+use super::ast::{All, Any, Expr, LicenseTerm, Var};
+
+use crate::parsing::{consume_tag, ensure, skip_space0, skip_tag, take_while1};
+
+pub use crate::parsing::{Error, MAX_DEPTH, Result};
+
+/// Parses an SPDX license expression. `WITH` binds tighter than `AND`, which binds tighter than `OR`.
+///
+/// Operators are matched case-insensitively.
+pub fn parse(mut s: &str) -> Result<'_, Expr> {
+    let s = &mut s;
+    skip_space0(s);
+    let expr = parse_or(s, 0)?;
+    skip_space0(s);
+    ensure!(s, s.is_empty());
+    Ok(expr)
+}
+
+fn is_id_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | ':')
+}
+
+fn skip_operator(s: &mut &str, op: &str) -> bool {
+    let matched = s
+        .get(..op.len())
+        .is_some_and(|t| t.eq_ignore_ascii_case(op));
+    if matched && !s[op.len()..].starts_with(is_id_char) {
+        *s = &s[op.len()..];
+        return true;
+    }
+    false
+}
+
+fn parse_or<'p>(s: &mut &'p str, depth: usize) -> Result<'p, Expr> {
+    let mut list = vec![parse_and(s, depth)?];
+    skip_space0(s);
+    while skip_operator(s, "OR") {
+        skip_space0(s);
+        list.push(parse_and(s, depth)?);
+        skip_space0(s);
+    }
+    Ok(if list.len() == 1 {
+        list.pop().unwrap()
+    } else {
+        Expr::Any(Any(list))
+    })
+}
+
+fn parse_and<'p>(s: &mut &'p str, depth: usize) -> Result<'p, Expr> {
+    let mut list = vec![parse_atom(s, depth)?];
+    skip_space0(s);
+    while skip_operator(s, "AND") {
+        skip_space0(s);
+        list.push(parse_atom(s, depth)?);
+        skip_space0(s);
+    }
+    Ok(if list.len() == 1 {
+        list.pop().unwrap()
+    } else {
+        Expr::All(All(list))
+    })
+}
+
+fn parse_atom<'p>(s: &mut &'p str, depth: usize) -> Result<'p, Expr> {
+    if s.starts_with('(') {
+        ensure!(s, depth < MAX_DEPTH);
+        consume_tag(s, "(")?;
+        skip_space0(s);
+        let expr = parse_or(s, depth + 1)?;
+        skip_space0(s);
+        consume_tag(s, ")")?;
+        return Ok(expr);
+    }
+    Ok(Expr::Var(Var(parse_term(s)?)))
+}
+
+fn parse_term<'p>(s: &mut &'p str) -> Result<'p, LicenseTerm> {
+    let license = parse_id(s)?;
+    let or_later = skip_tag(s, "+").is_some();
+
+    let mut rest = s.trim_start();
+    let exception = if skip_operator(&mut rest, "WITH") {
+        *s = rest;
+        skip_space0(s);
+        Some(parse_id(s)?.into())
+    } else {
+        None
+    };
+
+    Ok(LicenseTerm {
+        license: license.into(),
+        or_later,
+        exception,
+    })
+}
+
+fn parse_id<'p>(s: &mut &'p str) -> Result<'p, &'p str> {
+    let id = take_while1(s, is_id_char)?;
+    ensure!(
+        s,
+        !matches!(id, "AND" | "OR" | "WITH" | "and" | "or" | "with")
+    );
+    Ok(id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::{all, any, expr};
+    use crate::spdx::ast::{license, to_spdx, with_exception};
+    use crate::transforms::simplify;
+
+    #[test]
+    fn precedence() {
+        let x = parse("GPL-2.0-only WITH Classpath-exception-2.0 AND BSD-3-Clause OR MIT").unwrap();
+        let expected = expr(any((
+            all((
+                with_exception("GPL-2.0-only", "Classpath-exception-2.0"),
+                license("BSD-3-Clause"),
+            )),
+            license("MIT"),
+        )));
+        assert_eq!(x, expected);
+    }
+
+    #[test]
+    fn round_trip() {
+        let cases = [
+            ("MIT OR Apache-2.0", "MIT OR Apache-2.0"),
+            (
+                "(MIT or Apache-2.0) and GPL-2.0+",
+                "(MIT OR Apache-2.0) AND GPL-2.0+",
+            ),
+            ("MIT Or Apache-2.0 aNd ISC", "MIT OR Apache-2.0 AND ISC"),
+            ("((LicenseRef-Foo))", "LicenseRef-Foo"),
+            (
+                "DocumentRef-x:LicenseRef-y AND (A AND B)",
+                "DocumentRef-x:LicenseRef-y AND A AND B",
+            ),
+        ];
+        for (input, expected) in cases {
+            let x = parse(input).unwrap();
+            assert_eq!(to_spdx(&x).unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn errors() {
+        assert!(parse("MIT OR").is_err());
+        assert!(parse("MIT Apache-2.0").is_err());
+        assert!(parse("(MIT").is_err());
+        assert!(parse("MIT WITH").is_err());
+        assert!(parse("AND").is_err());
+        assert!(parse("MIT ORISC").is_err());
+    }
+
+    #[test]
+    fn simplify_duplicates() {
+        let mut x = parse("(MIT OR Apache-2.0) AND (MIT OR Apache-2.0) AND MIT").unwrap();
+        simplify(&mut x);
+        assert_eq!(to_spdx(&x).unwrap(), "MIT");
+    }
+
+    #[test]
+    fn depth_limit() {
+        let nested = |n| format!("{}MIT{}", "(".repeat(n), ")".repeat(n));
+        assert!(parse(&nested(MAX_DEPTH)).is_ok());
+        assert!(parse(&nested(MAX_DEPTH + 1)).is_err());
+        assert!(parse(&nested(1_000_000)).is_err());
+    }
+}
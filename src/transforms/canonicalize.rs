@@ -0,0 +1,71 @@
+use crate::ast::Expr;
+use crate::visit_mut::VisitMut;
+use crate::visit_mut::walk_mut_postorder;
+
+/// Sorts and dedups the children of every `any` and `all`,
+/// so that expressions equal up to reordering become identical.
+pub struct Canonicalize;
+
+impl<T> VisitMut<T> for Canonicalize
+where
+    T: Ord,
+{
+    fn visit_mut_expr(&mut self, expr: &mut Expr<T>) {
+        walk_mut_postorder(expr, &mut |expr| {
+            if let Some(list) = expr.as_mut_expr_list() {
+                list.sort();
+                list.dedup();
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::{all, any, const_, expr, not, var};
+
+    #[test]
+    fn canonicalize_reordered() {
+        let mut x: Expr<u32> = expr(any((var(2), var(1))));
+        let mut y: Expr<u32> = expr(any((var(1), var(2))));
+
+        Canonicalize.visit_mut_expr(&mut x);
+        Canonicalize.visit_mut_expr(&mut y);
+
+        assert_eq!(x, y);
+        assert_eq!(x.to_string(), "any(1, 2)");
+    }
+
+    #[test]
+    fn canonicalize_nested() {
+        let mut x: Expr<u32> = expr(all((
+            not(var(3)),
+            any((var(2), all((var(1), var(0))))),
+            const_(true),
+            any((all((var(0), var(1))), var(2))),
+        )));
+        let expected: Expr<u32> = expr(all((
+            any((all((var(0), var(1))), var(2))),
+            not(var(3)),
+            const_(true),
+        )));
+
+        Canonicalize.visit_mut_expr(&mut x);
+
+        assert_eq!(x.to_string(), expected.to_string());
+    }
+
+    #[test]
+    fn canonicalize_cfg() {
+        use crate::cfg::ast::{flag, target_os};
+
+        let mut x =
+            crate::cfg::ast::expr(any((target_os("macos"), flag("unix"), target_os("linux"))));
+        let expected = r#"any(target_os = "linux", target_os = "macos", unix)"#;
+
+        Canonicalize.visit_mut_expr(&mut x);
+
+        assert_eq!(x.to_string(), expected);
+    }
+}
@@ -1,6 +1,23 @@
 use crate::ast::Expr;
 use crate::visit_mut::VisitMut;
-use crate::visit_mut::walk_mut_expr;
+use crate::visit_mut::walk_mut_postorder;
+
+fn dedup_list<T: Eq>(expr: &mut Expr<T>) {
+    if let Some(list) = expr.as_mut_expr_list() {
+        let mut i = 0;
+        while i < list.len() {
+            let mut j = i + 1;
+            while j < list.len() {
+                if list[i] == list[j] {
+                    list.remove(j).drop_iterative();
+                } else {
+                    j += 1;
+                }
+            }
+            i += 1;
+        }
+    }
+}
 
 pub struct DedupList;
 
@@ -9,22 +26,7 @@ where
     T: Eq,
 {
     fn visit_mut_expr(&mut self, expr: &mut Expr<T>) {
-        walk_mut_expr(self, expr);
-
-        if let Some(list) = expr.as_mut_expr_list() {
-            let mut i = 0;
-            while i < list.len() {
-                let mut j = i + 1;
-                while j < list.len() {
-                    if list[i] == list[j] {
-                        list.remove(j);
-                    } else {
-                        j += 1;
-                    }
-                }
-                i += 1;
-            }
-        }
+        walk_mut_postorder(expr, &mut dedup_list);
     }
 }
 
@@ -1,9 +1,10 @@
+use std::mem;
 use std::ops::Not as _;
 
 use crate::ast::{All, Any, Expr, Not};
 use crate::utils::remove_if;
 use crate::visit_mut::VisitMut;
-use crate::visit_mut::walk_mut_expr;
+use crate::visit_mut::walk_mut_postorder;
 
 pub struct EvalConst;
 
@@ -42,26 +43,22 @@ impl EvalConst {
         }
         None
     }
-}
-
-impl<T> VisitMut<T> for EvalConst {
-    fn visit_mut_expr(&mut self, expr: &mut Expr<T>) {
-        walk_mut_expr(self, expr);
 
+    fn eval_node<T>(expr: &mut Expr<T>) {
         match expr {
             Expr::Any(Any(any)) => {
                 if let Some(val) = Self::eval_any(any) {
-                    *expr = Expr::Const(val);
+                    mem::replace(expr, Expr::Const(val)).drop_iterative();
                 }
             }
             Expr::All(All(all)) => {
                 if let Some(val) = Self::eval_all(all) {
-                    *expr = Expr::Const(val);
+                    mem::replace(expr, Expr::Const(val)).drop_iterative();
                 }
             }
             Expr::Not(Not(not)) => {
                 if let Some(val) = Self::eval_not(not) {
-                    *expr = Expr::Const(val);
+                    mem::replace(expr, Expr::Const(val)).drop_iterative();
                 }
             }
             _ => {}
@@ -69,6 +66,12 @@ impl<T> VisitMut<T> for EvalConst {
     }
 }
 
+impl<T> VisitMut<T> for EvalConst {
+    fn visit_mut_expr(&mut self, expr: &mut Expr<T>) {
+        walk_mut_postorder(expr, &mut Self::eval_node);
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
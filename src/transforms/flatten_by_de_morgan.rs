@@ -1,28 +1,58 @@
 use crate::ast::{All, Any, Expr, Not};
 use crate::visit_mut::VisitMut;
-use crate::visit_mut::walk_mut_expr;
 
-pub struct FlattenByDeMorgan;
+use std::mem;
 
-impl<T> VisitMut<T> for FlattenByDeMorgan {
-    fn visit_mut_expr(&mut self, expr: &mut Expr<T>) {
-        walk_mut_expr(self, expr);
-
-        if let Expr::Not(Not(not)) = expr {
-            match &mut **not {
-                Expr::Any(Any(any)) => {
-                    let list = any.drain(..).map(|x| Expr::Not(Not(Box::new(x)))).collect();
-                    *expr = Expr::All(All(list));
-                }
-                Expr::All(All(all)) => {
-                    let list = all.drain(..).map(|x| Expr::Not(Not(Box::new(x)))).collect();
-                    *expr = Expr::Any(Any(list));
-                }
-                _ => {}
+fn take<T>(expr: &mut Expr<T>) -> Expr<T> {
+    mem::replace(expr, Expr::Const(false))
+}
+
+/// Pushes negations down to the variables top-down, with an explicit stack.
+///
+/// Each node is visited with the number of negations pushed into it from above.
+fn push_down<T>(expr: &mut Expr<T>) {
+    let mut stack = vec![(expr, 0_usize)];
+    while let Some((expr, mut negs)) = stack.pop() {
+        let mut inner = &*expr;
+        let mut depth = 0;
+        while let Expr::Not(Not(not)) = inner {
+            inner = not;
+            depth += 1;
+        }
+
+        if !(inner.is_any() || inner.is_all()) {
+            for _ in 0..negs {
+                *expr = Expr::Not(Not(Box::new(take(expr))));
             }
+            continue;
         }
 
-        walk_mut_expr(self, expr);
+        for _ in 0..depth {
+            let Expr::Not(Not(not)) = expr else {
+                unreachable!()
+            };
+            let not = take(not);
+            *expr = not;
+        }
+        negs += depth;
+
+        if negs % 2 == 1 {
+            *expr = match expr {
+                Expr::Any(Any(any)) => Expr::All(All(mem::take(any))),
+                Expr::All(All(all)) => Expr::Any(Any(mem::take(all))),
+                _ => unreachable!(),
+            };
+        }
+        let list = expr.as_mut_expr_list().into_iter().flatten();
+        stack.extend(list.map(|x| (x, negs)));
+    }
+}
+
+pub struct FlattenByDeMorgan;
+
+impl<T> VisitMut<T> for FlattenByDeMorgan {
+    fn visit_mut_expr(&mut self, expr: &mut Expr<T>) {
+        push_down(expr);
     }
 }
 
@@ -165,4 +195,18 @@ mod tests {
 
         assert_eq!(x.to_string(), expected.to_string());
     }
+
+    #[test]
+    fn de_morgan_double_not() {
+        // not(not(any(a, b))) -> not(all(not(a), not(b))) -> any(not(not(a)), not(not(b)))
+        let mut x: Expr<u32> = expr(not(not(any((var(1), all((var(2), var(3))))))));
+        let expected: Expr<u32> = expr(any((
+            not(not(var(1))),
+            all((not(not(var(2))), not(not(var(3))))),
+        )));
+
+        FlattenByDeMorgan.visit_mut_expr(&mut x);
+
+        assert_eq!(x.to_string(), expected.to_string());
+    }
 }
@@ -1,40 +1,54 @@
 use crate::ast::{All, Any, Expr};
 use crate::visit_mut::VisitMut;
-use crate::visit_mut::walk_mut_expr_list;
+use crate::visit_mut::{walk_mut_expr_list, walk_mut_postorder};
+
+fn flatten_any<T>(list: &mut Vec<Expr<T>>) {
+    if list.iter().any(Expr::is_any) {
+        let mut ans: Vec<Expr<T>> = Vec::with_capacity(list.len());
+        for item in list.drain(..) {
+            if let Expr::Any(Any(any)) = item {
+                ans.extend(any);
+            } else {
+                ans.push(item);
+            }
+        }
+        *list = ans;
+    }
+}
+
+fn flatten_all<T>(list: &mut Vec<Expr<T>>) {
+    if list.iter().any(Expr::is_all) {
+        let mut ans: Vec<Expr<T>> = Vec::with_capacity(list.len());
+        for item in list.drain(..) {
+            if let Expr::All(All(all)) = item {
+                ans.extend(all);
+            } else {
+                ans.push(item);
+            }
+        }
+        *list = ans;
+    }
+}
 
 pub struct FlattenNestedList;
 
 impl<T> VisitMut<T> for FlattenNestedList {
+    fn visit_mut_expr(&mut self, expr: &mut Expr<T>) {
+        walk_mut_postorder(expr, &mut |expr| match expr {
+            Expr::Any(Any(list)) => flatten_any(list),
+            Expr::All(All(list)) => flatten_all(list),
+            _ => {}
+        });
+    }
+
     fn visit_mut_any(&mut self, Any(list): &mut Any<T>) {
         walk_mut_expr_list(self, list);
-
-        if list.iter().any(Expr::is_any) {
-            let mut ans: Vec<Expr<T>> = Vec::with_capacity(list.len());
-            for item in list.drain(..) {
-                if let Expr::Any(Any(any)) = item {
-                    ans.extend(any);
-                } else {
-                    ans.push(item);
-                }
-            }
-            *list = ans;
-        }
+        flatten_any(list);
     }
 
     fn visit_mut_all(&mut self, All(list): &mut All<T>) {
         walk_mut_expr_list(self, list);
-
-        if list.iter().any(Expr::is_all) {
-            let mut ans: Vec<Expr<T>> = Vec::with_capacity(list.len());
-            for item in list.drain(..) {
-                if let Expr::All(All(all)) = item {
-                    ans.extend(all);
-                } else {
-                    ans.push(item);
-                }
-            }
-            *list = ans;
-        }
+        flatten_all(list);
     }
 }
 
@@ -2,35 +2,37 @@
 
 use crate::ast::{All, Any, Expr, Not};
 use crate::visit_mut::VisitMut;
-use crate::visit_mut::walk_mut_expr;
+use crate::visit_mut::walk_mut_postorder;
 
 fn take<T>(expr: &mut Expr<T>) -> Expr<T> {
     std::mem::replace(expr, Expr::Const(false))
 }
 
+fn flatten_single<T>(expr: &mut Expr<T>) {
+    match expr {
+        Expr::Any(Any(any)) => match any.as_mut_slice() {
+            [] => *expr = Expr::Const(false),
+            [sub] => *expr = take(sub),
+            _ => {}
+        },
+        Expr::All(All(all)) => match all.as_mut_slice() {
+            [] => *expr = Expr::Const(true),
+            [sub] => *expr = take(sub),
+            _ => {}
+        },
+        Expr::Not(Not(not)) => match &mut **not {
+            Expr::Not(Not(sub)) => *expr = take(sub),
+            _ => {}
+        },
+        _ => {}
+    }
+}
+
 pub struct FlattenSingle;
 
 impl<T> VisitMut<T> for FlattenSingle {
     fn visit_mut_expr(&mut self, expr: &mut Expr<T>) {
-        walk_mut_expr(self, expr);
-
-        match expr {
-            Expr::Any(Any(any)) => match any.as_mut_slice() {
-                [] => *expr = Expr::Const(false),
-                [sub] => *expr = take(sub),
-                _ => {}
-            },
-            Expr::All(All(all)) => match all.as_mut_slice() {
-                [] => *expr = Expr::Const(true),
-                [sub] => *expr = take(sub),
-                _ => {}
-            },
-            Expr::Not(Not(not)) => match &mut **not {
-                Expr::Not(Not(sub)) => *expr = take(sub),
-                _ => {}
-            },
-            _ => {}
-        }
+        walk_mut_postorder(expr, &mut flatten_single);
     }
 }
 
@@ -1,6 +1,8 @@
-use crate::ast::{All, Any, Expr};
+use crate::ast::{All, Any, Expr, drop_deep};
 use crate::utils::remove_if;
-use crate::visit_mut::{VisitMut, walk_mut_expr_list};
+
+use crate::visit_mut::{VisitMut, walk_mut_expr_list, walk_mut_postorder};
+use std::mem;
 
 fn is_subset_of<T: Eq>(lhs: &[Expr<T>], rhs: &[Expr<T>]) -> bool {
     lhs.iter().all(|x| rhs.contains(x))
@@ -11,34 +13,45 @@ fn get2_mut<T>(slice: &mut [T], i: usize, j: usize) -> Option<(&mut T, &mut T)>
     Some((lhs, rhs))
 }
 
-pub struct MergeAllOfAny;
-
-impl<T: Eq> VisitMut<T> for MergeAllOfAny {
-    fn visit_mut_all(&mut self, All(all): &mut All<T>) {
-        walk_mut_expr_list(self, all);
-
-        let mut any_list: Vec<_> = all
-            .iter_mut()
-            .filter_map(|x| Expr::as_mut_any(x).map(|x| &mut x.0))
-            .collect();
-
-        for i in 0..any_list.len() {
-            for j in 0..any_list.len() {
-                if i != j {
-                    let (lhs, rhs) = get2_mut(&mut any_list, i, j).unwrap();
-                    if is_subset_of(lhs, rhs) {
-                        rhs.clear();
-                        rhs.push(Expr::Const(true));
-                    }
+fn merge_all_of_any<T: Eq>(all: &mut Vec<Expr<T>>) {
+    let mut any_list: Vec<_> = all
+        .iter_mut()
+        .filter_map(|x| Expr::as_mut_any(x).map(|x| &mut x.0))
+        .collect();
+
+    for i in 0..any_list.len() {
+        for j in 0..any_list.len() {
+            if i != j {
+                let (lhs, rhs) = get2_mut(&mut any_list, i, j).unwrap();
+                if is_subset_of(lhs, rhs) {
+                    drop_deep(mem::take(&mut **rhs));
+                    rhs.push(Expr::Const(true));
                 }
             }
         }
+    }
+
+    remove_if(all, |x| match x {
+        Expr::Any(Any(any)) => matches!(any.as_slice(), [Expr::Const(true)]),
+        _ => false,
+    });
+}
+
+pub struct MergeAllOfAny;
 
-        remove_if(all, |x| match x {
-            Expr::Any(Any(any)) => matches!(any.as_slice(), [Expr::Const(true)]),
-            _ => false,
+impl<T: Eq> VisitMut<T> for MergeAllOfAny {
+    fn visit_mut_expr(&mut self, expr: &mut Expr<T>) {
+        walk_mut_postorder(expr, &mut |expr| {
+            if let Expr::All(All(all)) = expr {
+                merge_all_of_any(all);
+            }
         });
     }
+
+    fn visit_mut_all(&mut self, All(all): &mut All<T>) {
+        walk_mut_expr_list(self, all);
+        merge_all_of_any(all);
+    }
 }
 
 #[cfg(test)]
@@ -2,7 +2,7 @@ use std::ops::Not as _;
 
 use crate::ast::{All, Expr, Not, Var};
 use crate::utils::{drain_filter, remove_if};
-use crate::visit_mut::{VisitMut, walk_mut_expr_list};
+use crate::visit_mut::{VisitMut, walk_mut_expr_list, walk_mut_postorder};
 
 fn as_mut_not_any<T>(expr: &mut Expr<T>) -> Option<&mut Vec<Expr<T>>> {
     expr.as_mut_not_any().map(|x| &mut x.0)
@@ -17,32 +17,43 @@ fn unwrap_expr_not_var<T>(expr: Expr<T>) -> Var<T> {
     panic!()
 }
 
-pub struct MergeAllOfNotAny;
+fn merge_all_of_not_any<T>(all: &mut Vec<Expr<T>>) {
+    let mut not_any_list: Vec<_> = all.iter_mut().filter_map(as_mut_not_any).collect();
 
-impl<T> VisitMut<T> for MergeAllOfNotAny {
-    fn visit_mut_all(&mut self, All(all): &mut All<T>) {
-        walk_mut_expr_list(self, all);
+    if let [first, rest @ ..] = not_any_list.as_mut_slice() {
+        if rest.is_empty().not() {
+            for x in rest {
+                first.append(x);
+            }
+            remove_if(all, Expr::is_empty_not_any);
+        }
 
-        let mut not_any_list: Vec<_> = all.iter_mut().filter_map(as_mut_not_any).collect();
+        {
+            let not_var_list: Vec<_> = drain_filter(all, |x| x.is_expr_not_var()).collect();
+            let not_any = all.iter_mut().find_map(as_mut_not_any).unwrap();
 
-        if let [first, rest @ ..] = not_any_list.as_mut_slice() {
-            if rest.is_empty().not() {
-                for x in rest {
-                    first.append(x);
-                }
-                remove_if(all, Expr::is_empty_not_any);
+            for not_var in not_var_list {
+                let var = unwrap_expr_not_var(not_var);
+                not_any.push(Expr::Var(var));
             }
+        }
+    }
+}
 
-            {
-                let not_var_list: Vec<_> = drain_filter(all, |x| x.is_expr_not_var()).collect();
-                let not_any = all.iter_mut().find_map(as_mut_not_any).unwrap();
+pub struct MergeAllOfNotAny;
 
-                for not_var in not_var_list {
-                    let var = unwrap_expr_not_var(not_var);
-                    not_any.push(Expr::Var(var));
-                }
+impl<T> VisitMut<T> for MergeAllOfNotAny {
+    fn visit_mut_expr(&mut self, expr: &mut Expr<T>) {
+        walk_mut_postorder(expr, &mut |expr| {
+            if let Expr::All(All(all)) = expr {
+                merge_all_of_not_any(all);
             }
-        }
+        });
+    }
+
+    fn visit_mut_all(&mut self, All(all): &mut All<T>) {
+        walk_mut_expr_list(self, all);
+        merge_all_of_not_any(all);
     }
 }
 
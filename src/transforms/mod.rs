@@ -1,3 +1,4 @@
+pub mod canonicalize;
 pub mod dedup_list;
 
 pub mod flatten_by_de_morgan;
@@ -12,3 +13,90 @@ pub mod simplify_nested_list;
 
 pub mod merge_all_of_any;
 pub mod merge_all_of_not_any;
+
+use crate::ast::Expr;
+use crate::visit_mut::VisitMut;
+
+use self::dedup_list::DedupList;
+use self::eval_const::EvalConst;
+use self::flatten_by_de_morgan::FlattenByDeMorgan;
+use self::flatten_nested_list::FlattenNestedList;
+use self::flatten_single::FlattenSingle;
+use self::simplify_by_short_circuit::SimplifyByShortCircuit;
+use self::simplify_nested_list::SimplifyNestedList;
+
+/// Applies the equivalence-preserving transforms until the expression stops changing.
+///
+/// Negations are pushed down to the variables, so the result contains no `not(any(..))` or `not(all(..))`.
+pub fn simplify<T>(expr: &mut Expr<T>)
+where
+    T: Clone + Eq,
+{
+    loop {
+        let prev = expr.clone();
+
+        FlattenByDeMorgan.visit_mut_expr(expr);
+        FlattenSingle.visit_mut_expr(expr);
+        FlattenNestedList.visit_mut_expr(expr);
+        DedupList.visit_mut_expr(expr);
+        SimplifyByShortCircuit.visit_mut_expr(expr);
+        EvalConst.visit_mut_expr(expr);
+        SimplifyNestedList.visit_mut_expr(expr);
+        FlattenSingle.visit_mut_expr(expr);
+
+        let done = *expr == prev;
+        prev.drop_iterative();
+        if done {
+            break;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::{all, any, const_, expr, not, var};
+
+    #[test]
+    fn simplify_not_any() {
+        let mut x: Expr<u32> = expr(all((not(any((var(0), var(1)))), var(2))));
+        let expected: Expr<u32> = expr(all((not(var(0)), not(var(1)), var(2))));
+
+        simplify(&mut x);
+
+        assert_eq!(x.to_string(), expected.to_string());
+    }
+
+    #[test]
+    fn simplify_to_const() {
+        let mut x: Expr<u32> = expr(any((var(1), not(all((const_(true), not(any(()))))))));
+        let expected: Expr<u32> = expr(var(1));
+
+        simplify(&mut x);
+
+        assert_eq!(x.to_string(), expected.to_string());
+    }
+
+    #[test]
+    fn simplify_deeply_nested() {
+        let mut x: Expr<u32> = expr(var(0));
+        for _ in 0..300_000 {
+            x = expr(not(x));
+        }
+        simplify(&mut x);
+        assert_eq!(x, expr(var(0)));
+
+        let mut x: Expr<u32> = expr(var(0));
+        for i in 1..100_000 {
+            x = expr(all((any((x, var(2 * i))), var(2 * i + 1))));
+        }
+        let expected = x.clone();
+        simplify(&mut x);
+        assert_eq!(x, expected);
+        expected.drop_iterative();
+
+        let mut x = expr(any((x, const_(true))));
+        simplify(&mut x);
+        assert_eq!(x, const_(true));
+    }
+}
@@ -3,7 +3,21 @@ use std::slice;
 use crate::ast::{All, Any, Expr, Not};
 use crate::utils::remove_if;
 use crate::visit_mut::VisitMut;
-use crate::visit_mut::walk_mut_expr_list;
+use crate::visit_mut::{walk_mut_expr_list, walk_mut_postorder};
+
+/// `all(not(any(x0, x1, ...)), any(x0, x2, ...)) => all(not(any(...)), any(x0, ...))`
+fn simplify_all_not_any<T: Eq>(all: &mut [Expr<T>]) {
+    match all {
+        [Expr::Any(Any(pos)), Expr::Not(Not(not))] | [Expr::Not(Not(not)), Expr::Any(Any(pos))] => {
+            let neg = match not.as_mut_any() {
+                Some(Any(neg)) => neg,
+                None => slice::from_mut(&mut **not),
+            };
+            remove_if(pos, |x| neg.contains(x));
+        }
+        _ => {}
+    }
+}
 
 pub struct SimplifyAllNotAny;
 
@@ -11,21 +25,17 @@ impl<T> VisitMut<T> for SimplifyAllNotAny
 where
     T: Eq,
 {
-    /// `all(not(any(x0, x1, ...)), any(x0, x2, ...)) => all(not(any(...)), any(x0, ...))`
+    fn visit_mut_expr(&mut self, expr: &mut Expr<T>) {
+        walk_mut_postorder(expr, &mut |expr| {
+            if let Expr::All(All(all)) = expr {
+                simplify_all_not_any(all);
+            }
+        });
+    }
+
     fn visit_mut_all(&mut self, All(all): &mut All<T>) {
         walk_mut_expr_list(self, all);
-
-        match all.as_mut_slice() {
-            [Expr::Any(Any(pos)), Expr::Not(Not(not))]
-            | [Expr::Not(Not(not)), Expr::Any(Any(pos))] => {
-                let neg = match not.as_mut_any() {
-                    Some(Any(neg)) => neg,
-                    None => slice::from_mut(&mut **not),
-                };
-                remove_if(pos, |x| neg.contains(x));
-            }
-            _ => {}
-        }
+        simplify_all_not_any(all);
     }
 }
 
@@ -18,22 +18,36 @@ fn find_vars<T: Eq + Clone>(list: &mut [Expr<T>], marker: bool) -> Vec<Var<T>> {
 }
 
 fn replace_vars<T: Eq>(x: &mut Expr<T>, vars: &[Var<T>], marker: bool) {
-    match x {
-        Expr::Any(Any(any)) => any.iter_mut().for_each(|x| replace_vars(x, vars, marker)),
-        Expr::All(All(all)) => all.iter_mut().for_each(|x| replace_vars(x, vars, marker)),
-        Expr::Not(Not(not)) => replace_vars(not, vars, marker),
-        Expr::Var(var) => {
+    let mut stack = vec![x];
+    while let Some(x) = stack.pop() {
+        if let Expr::Var(var) = x {
             if vars.contains(var) {
                 *x = Expr::Const(marker);
             }
+            continue;
+        }
+        match x {
+            Expr::Any(Any(list)) | Expr::All(All(list)) => stack.extend(list),
+            Expr::Not(Not(not)) => stack.push(not),
+            Expr::Var(_) | Expr::Const(_) => {}
         }
-        Expr::Const(_) => {}
     }
 }
 
 pub struct SimplifyByShortCircuit;
 
 impl<T: Eq + Clone> VisitMut<T> for SimplifyByShortCircuit {
+    fn visit_mut_expr(&mut self, mut expr: &mut Expr<T>) {
+        loop {
+            match expr {
+                Expr::Any(any) => break self.visit_mut_any(any),
+                Expr::All(all) => break self.visit_mut_all(all),
+                Expr::Not(Not(not)) => expr = not,
+                Expr::Var(_) | Expr::Const(_) => break,
+            }
+        }
+    }
+
     fn visit_mut_any(&mut self, Any(any): &mut Any<T>) {
         let marker = false;
         let vars = find_vars(any, marker);
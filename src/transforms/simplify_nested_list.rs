@@ -1,49 +1,63 @@
 use crate::ast::{All, Any, Expr};
 use crate::visit_mut::VisitMut;
-use crate::visit_mut::walk_mut_expr_list;
+use crate::visit_mut::{walk_mut_expr_list, walk_mut_postorder};
 
 fn contains_cross_same<T: Eq>(lhs: &[T], rhs: &[T]) -> bool {
     lhs.iter().any(|x| rhs.contains(x))
 }
 
+/// `any(x0, all(x0, x1), x2) => any(x0, x2)`
+fn simplify_any<T: Eq>(any: &mut Vec<Expr<T>>) {
+    let mut i = 0;
+    while i < any.len() {
+        if let Expr::All(All(all)) = &any[i] {
+            if contains_cross_same(all, any) {
+                any.remove(i).drop_iterative();
+                continue;
+            }
+        }
+
+        i += 1;
+    }
+}
+
+/// `all(x0, any(x0, x1), x2) => all(x0, x2)`
+fn simplify_all<T: Eq>(all: &mut Vec<Expr<T>>) {
+    let mut i = 0;
+    while i < all.len() {
+        if let Expr::Any(Any(any)) = &all[i] {
+            if contains_cross_same(any, all) {
+                all.remove(i).drop_iterative();
+                continue;
+            }
+        }
+
+        i += 1;
+    }
+}
+
 pub struct SimplifyNestedList;
 
 impl<T> VisitMut<T> for SimplifyNestedList
 where
     T: Eq,
 {
-    /// `any(x0, all(x0, x1), x2) => any(x0, x2)`
+    fn visit_mut_expr(&mut self, expr: &mut Expr<T>) {
+        walk_mut_postorder(expr, &mut |expr| match expr {
+            Expr::Any(Any(any)) => simplify_any(any),
+            Expr::All(All(all)) => simplify_all(all),
+            _ => {}
+        });
+    }
+
     fn visit_mut_any(&mut self, Any(any): &mut Any<T>) {
         walk_mut_expr_list(self, any);
-
-        let mut i = 0;
-        while i < any.len() {
-            if let Expr::All(All(all)) = &any[i] {
-                if contains_cross_same(all, any) {
-                    any.remove(i);
-                    continue;
-                }
-            }
-
-            i += 1;
-        }
+        simplify_any(any);
     }
 
-    /// `all(x0, any(x0, x1), x2) => all(x0, x2)`
     fn visit_mut_all(&mut self, All(all): &mut All<T>) {
         walk_mut_expr_list(self, all);
-
-        let mut i = 0;
-        while i < all.len() {
-            if let Expr::Any(Any(any)) = &all[i] {
-                if contains_cross_same(any, all) {
-                    all.remove(i);
-                    continue;
-                }
-            }
-
-            i += 1;
-        }
+        simplify_all(all);
     }
 }
 
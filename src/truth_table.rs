@@ -0,0 +1,331 @@
+//! Truth tables of expressions.
+
+use crate::ast::Expr;
+use crate::eval::eval_with;
+
+use std::fmt::{self, Write as _};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// Aligned columns for terminals.
+    Text,
+    /// A GitHub-flavored markdown table.
+    Markdown,
+    /// Comma-separated values with a header line.
+    Csv,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Row {
+    /// The value of each variable, in the order of [`TruthTable::vars`].
+    pub assignment: Vec<bool>,
+    pub result: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TruthTable<'a, T> {
+    pub vars: Vec<&'a T>,
+    pub rows: Vec<Row>,
+}
+
+/// Builds the truth table of `expr` over its variables in first-occurrence order.
+///
+/// Rows count up from all-false with the first variable as the most significant bit.
+///
+/// # Panics
+/// Panics if the expression has too many variables to enumerate.
+#[must_use]
+pub fn truth_table<T>(expr: &Expr<T>) -> TruthTable<'_, T>
+where
+    T: Eq,
+{
+    let vars = expr.vars();
+    let rows = assignments(vars.len())
+        .map(|assignment| {
+            let result = eval_with(expr, &|x| lookup(&vars, &assignment, x));
+            Row { assignment, result }
+        })
+        .collect();
+    TruthTable { vars, rows }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DiffRow {
+    pub assignment: Vec<bool>,
+    pub lhs: bool,
+    pub rhs: bool,
+}
+
+impl DiffRow {
+    #[must_use]
+    pub fn differs(&self) -> bool {
+        self.lhs != self.rhs
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TruthTableDiff<'a, T> {
+    pub vars: Vec<&'a T>,
+    pub rows: Vec<DiffRow>,
+}
+
+/// Builds the truth tables of two expressions over the union of their variables.
+///
+/// # Panics
+/// Panics if the expressions have too many variables to enumerate.
+#[must_use]
+pub fn diff<'a, T>(lhs: &'a Expr<T>, rhs: &'a Expr<T>) -> TruthTableDiff<'a, T>
+where
+    T: Eq,
+{
+    let mut vars = lhs.vars();
+    for x in rhs.vars() {
+        if !vars.contains(&x) {
+            vars.push(x);
+        }
+    }
+    let rows = assignments(vars.len())
+        .map(|assignment| {
+            let f = |x: &T| lookup(&vars, &assignment, x);
+            let (lhs, rhs) = (eval_with(lhs, &f), eval_with(rhs, &f));
+            DiffRow {
+                assignment,
+                lhs,
+                rhs,
+            }
+        })
+        .collect();
+    TruthTableDiff { vars, rows }
+}
+
+impl<T> TruthTableDiff<'_, T> {
+    /// Returns the rows where the expressions disagree.
+    pub fn disagreements(&self) -> impl Iterator<Item = &DiffRow> {
+        self.rows.iter().filter(|r| r.differs())
+    }
+
+    #[must_use]
+    pub fn is_equivalent(&self) -> bool {
+        self.disagreements().next().is_none()
+    }
+}
+
+fn assignments(n: usize) -> impl Iterator<Item = Vec<bool>> {
+    assert!(n < usize::BITS as usize, "too many variables: {n}");
+    (0..1_usize << n).map(move |i| (0..n).map(|j| (i >> (n - 1 - j)) & 1 == 1).collect())
+}
+
+fn lookup<T: Eq>(vars: &[&T], assignment: &[bool], x: &T) -> bool {
+    let pos = vars.iter().position(|v| *v == x);
+    assignment[pos.unwrap()]
+}
+
+impl<T> TruthTable<'_, T>
+where
+    T: fmt::Display,
+{
+    #[must_use]
+    pub fn render(&self, format: Format) -> String {
+        let mut headers: Vec<String> = self.vars.iter().map(ToString::to_string).collect();
+        headers.push("result".to_owned());
+        let rows = self.rows.iter().map(|r| {
+            let cells = r.assignment.iter().chain([&r.result]).copied().collect();
+            (cells, false)
+        });
+        render(format, &headers, rows, None)
+    }
+}
+
+impl<T> TruthTableDiff<'_, T>
+where
+    T: fmt::Display,
+{
+    /// Renders the table, highlighting the rows where the expressions disagree.
+    ///
+    /// Text rows are marked with `*`, markdown rows are bold and CSV gets a `differs` column.
+    #[must_use]
+    pub fn render(&self, format: Format) -> String {
+        let mut headers: Vec<String> = self.vars.iter().map(ToString::to_string).collect();
+        headers.extend(["lhs".to_owned(), "rhs".to_owned()]);
+        let rows = self.rows.iter().map(|r| {
+            let cells = r
+                .assignment
+                .iter()
+                .chain([&r.lhs, &r.rhs])
+                .copied()
+                .collect();
+            (cells, r.differs())
+        });
+        render(format, &headers, rows, Some("differs"))
+    }
+}
+
+impl<T> fmt::Display for TruthTable<'_, T>
+where
+    T: fmt::Display,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.render(Format::Text))
+    }
+}
+
+impl<T> fmt::Display for TruthTableDiff<'_, T>
+where
+    T: fmt::Display,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.render(Format::Text))
+    }
+}
+
+fn render(
+    format: Format,
+    headers: &[String],
+    rows: impl Iterator<Item = (Vec<bool>, bool)>,
+    highlight: Option<&str>,
+) -> String {
+    let bit = |b: bool| if b { "1" } else { "0" };
+    let mut out = String::new();
+    match format {
+        Format::Text => {
+            let widths: Vec<usize> = headers.iter().map(|h| h.chars().count()).collect();
+            let line = |cells: &mut dyn Iterator<Item = &str>| {
+                let cells: Vec<String> = cells
+                    .zip(&widths)
+                    .map(|(c, &w)| format!("{c:<w$}"))
+                    .collect();
+                cells.join(" | ")
+            };
+            out.push_str(line(&mut headers.iter().map(String::as_str)).trim_end());
+            out.push('\n');
+            let sep: Vec<String> = widths.iter().map(|&w| "-".repeat(w)).collect();
+            out.push_str(&sep.join("-+-"));
+            out.push('\n');
+            for (cells, marked) in rows {
+                let s = line(&mut cells.into_iter().map(bit));
+                out.push_str(s.trim_end());
+                if marked {
+                    out.push_str(" *");
+                }
+                out.push('\n');
+            }
+        }
+        Format::Markdown => {
+            let escape = |s: &str| s.replace('|', "\\|");
+            let headers: Vec<String> = headers.iter().map(|h| escape(h)).collect();
+            writeln!(out, "| {} |", headers.join(" | ")).unwrap();
+            writeln!(out, "|{}", "---|".repeat(headers.len())).unwrap();
+            for (cells, marked) in rows {
+                let cells: Vec<&str> = cells.into_iter().map(bit).collect();
+                if marked {
+                    writeln!(out, "| **{}** |", cells.join("** | **")).unwrap();
+                } else {
+                    writeln!(out, "| {} |", cells.join(" | ")).unwrap();
+                }
+            }
+        }
+        Format::Csv => {
+            let quote = |s: &str| {
+                if s.contains([',', '"', '\n', '\r']) {
+                    format!("\"{}\"", s.replace('"', "\"\""))
+                } else {
+                    s.to_owned()
+                }
+            };
+            let mut headers: Vec<String> = headers.iter().map(|h| quote(h)).collect();
+            headers.extend(highlight.map(str::to_owned));
+            writeln!(out, "{}", headers.join(",")).unwrap();
+            for (cells, marked) in rows {
+                let mut cells: Vec<&str> = cells.into_iter().map(bit).collect();
+                if highlight.is_some() {
+                    cells.push(bit(marked));
+                }
+                writeln!(out, "{}", cells.join(",")).unwrap();
+            }
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::{all, any, expr, not, var};
+    use crate::cfg::ast::{flag, target_os};
+
+    #[test]
+    fn rows() {
+        let x = expr(all((var("a"), not(var("b")))));
+        let t = truth_table(&x);
+        assert_eq!(t.vars, [&"a", &"b"]);
+        let results: Vec<bool> = t.rows.iter().map(|r| r.result).collect();
+        assert_eq!(results, [false, false, true, false]);
+        assert_eq!(t.rows[2].assignment, [true, false]);
+    }
+
+    #[test]
+    fn formats() {
+        let x = expr(any((var("a"), var("bb"))));
+        let t = truth_table(&x);
+
+        let text = "\
+a | bb | result
+--+----+-------
+0 | 0  | 0
+0 | 1  | 1
+1 | 0  | 1
+1 | 1  | 1
+";
+        assert_eq!(t.to_string(), text);
+
+        let markdown = "\
+| a | bb | result |
+|---|---|---|
+| 0 | 0 | 0 |
+| 0 | 1 | 1 |
+| 1 | 0 | 1 |
+| 1 | 1 | 1 |
+";
+        assert_eq!(t.render(Format::Markdown), markdown);
+
+        let x = expr(all((flag("unix"), target_os("linux"))));
+        let csv = "\
+unix,\"target_os = \"\"linux\"\"\",result
+0,0,0
+0,1,0
+1,0,0
+1,1,1
+";
+        assert_eq!(truth_table(&x).render(Format::Csv), csv);
+    }
+
+    #[test]
+    fn diff_cfg_if() {
+        let (x0, x1, x2) = (var(0), var(1), var(2));
+        let src = expr(all((not(any((x0, x1))), x2)));
+        let dst = expr(all((not(x0), not(x1), x2)));
+        assert!(diff(&src, &dst).is_equivalent());
+
+        let lhs = expr(any((var(0), var(1))));
+        let rhs = expr(all((var(0), var(1))));
+        let d = diff(&lhs, &rhs);
+        assert_eq!(d.disagreements().count(), 2);
+
+        let text = "\
+0 | 1 | lhs | rhs
+--+---+-----+----
+0 | 0 | 0   | 0
+0 | 1 | 1   | 0 *
+1 | 0 | 1   | 0 *
+1 | 1 | 1   | 1
+";
+        assert_eq!(d.to_string(), text);
+
+        let csv = "0,1,lhs,rhs,differs\n0,0,0,0,0\n0,1,1,0,1\n1,0,1,0,1\n1,1,1,1,0\n";
+        assert_eq!(d.render(Format::Csv), csv);
+        assert!(
+            d.render(Format::Markdown)
+                .contains("| **0** | **1** | **1** | **0** |")
+        );
+    }
+}
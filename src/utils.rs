@@ -1,5 +1,14 @@
-pub fn remove_if<T>(v: &mut Vec<T>, f: impl Fn(&T) -> bool) {
-    v.retain(|expr| !f(expr));
+use crate::ast::{Expr, drop_deep, fold_ref};
+
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::mem;
+
+/// Removes the expressions matching `f`, dropping them with an explicit stack.
+pub fn remove_if<T>(v: &mut Vec<Expr<T>>, f: impl Fn(&Expr<T>) -> bool) {
+    let (removed, kept) = mem::take(v).into_iter().partition(|expr| f(expr));
+    *v = kept;
+    drop_deep(removed);
 }
 
 pub fn drain_filter<'a, T, F>(v: &'a mut Vec<T>, mut f: F) -> impl Iterator<Item = T> + 'a
@@ -17,3 +26,19 @@ where
         None
     })
 }
+
+/// Numbers the distinct variables of `expr` in order of first occurrence, like [`Expr::vars`],
+/// and replaces them by their number.
+pub fn index_vars<T: Eq + Hash>(expr: &Expr<T>) -> (Vec<&T>, Expr<usize>) {
+    let mut vars = Vec::new();
+    let mut index = HashMap::new();
+    let ans = fold_ref(expr, &mut |node| {
+        node.into_expr(|x| {
+            *index.entry(x).or_insert_with(|| {
+                vars.push(x);
+                vars.len() - 1
+            })
+        })
+    });
+    (vars, ans)
+}
@@ -1,5 +1,11 @@
 use crate::ast::{All, Any, Expr, Not, Var};
 
+use std::mem;
+
+/// A mutable visitor, walking the expression recursively by default.
+///
+/// The built-in transforms override [`visit_mut_expr`](Self::visit_mut_expr) with an explicit stack,
+/// so they do not overflow the stack on deeply nested expressions.
 pub trait VisitMut<T> {
     fn visit_mut_expr(&mut self, expr: &mut Expr<T>) {
         walk_mut_expr(self, expr);
@@ -50,3 +56,58 @@ where
 {
     v.visit_mut_expr(not);
 }
+
+/// Calls `f` on every node after its children, with an explicit stack instead of recursion.
+///
+/// Unlike [`VisitMut`], this cannot overflow the stack on deeply nested expressions.
+/// Each child is detached from its parent while it is visited, so no node is reallocated.
+pub fn walk_mut_postorder<T, F>(expr: &mut Expr<T>, f: &mut F)
+where
+    F: FnMut(&mut Expr<T>),
+{
+    /// A node whose child `next - 1` is detached.
+    struct Frame<T> {
+        node: Expr<T>,
+        next: usize,
+    }
+
+    fn child<T>(node: &mut Expr<T>, i: usize) -> Option<&mut Expr<T>> {
+        match node {
+            Expr::Any(Any(list)) | Expr::All(All(list)) => list.get_mut(i),
+            Expr::Not(Not(not)) => (i == 0).then_some(&mut **not),
+            Expr::Var(_) | Expr::Const(_) => None,
+        }
+    }
+
+    fn take<T>(expr: &mut Expr<T>) -> Expr<T> {
+        mem::replace(expr, Expr::Const(false))
+    }
+
+    let mut stack: Vec<Frame<T>> = Vec::new();
+    let mut cur = take(expr);
+    loop {
+        while let Some(first) = child(&mut cur, 0).map(take) {
+            stack.push(Frame { node: cur, next: 1 });
+            cur = first;
+        }
+        f(&mut cur);
+        loop {
+            let Some(mut frame) = stack.pop() else {
+                *expr = cur;
+                return;
+            };
+            let Some(slot) = child(&mut frame.node, frame.next - 1) else {
+                unreachable!()
+            };
+            *slot = cur;
+            if let Some(next) = child(&mut frame.node, frame.next).map(take) {
+                frame.next += 1;
+                stack.push(frame);
+                cur = next;
+                break;
+            }
+            cur = frame.node;
+            f(&mut cur);
+        }
+    }
+}